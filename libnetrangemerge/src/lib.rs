@@ -88,15 +88,47 @@
 //! method is unavailable as are the built in range types. The application can implement
 //! its own type that implements the [`Range`] trait and pass instances of that
 //! type to [`merge_ranges_slice`] for merging.
+//!
+//! # Lookup Tables
+//!
+//! [`write_lookup_table`] writes a set of ranges in a compact, versioned binary
+//! format that [`LookupTable`] can query without copying or parsing the data, which
+//! makes it suitable for memory mapping. All integers are stored in little-endian
+//! byte order.
+//!
+//! ## Layout
+//!
+//! | Offset | Size | Description                                          |
+//! |--------|------|------------------------------------------------------|
+//! | 0      | 4    | Magic bytes: `NRLT`                                  |
+//! | 4      | 2    | Format version: `1`                                  |
+//! | 6      | 2    | Reserved, must be `0`                                |
+//! | 8      | 4    | Number of ipv4 intervals                             |
+//! | 12     | 4    | Number of ipv6 intervals                             |
+//! | 16     | 4    | CRC-32 (IEEE) of every byte following the header     |
+//! | 20     | 12   | Reserved, must be `0`                                |
+//! | 32     | 8n   | ipv4 intervals                                       |
+//! | ...    | 0-8  | Padding so that the ipv6 intervals are 16 byte aligned |
+//! | ...    | 32n  | ipv6 intervals                                       |
+//!
+//! Each interval is a pair of the first and last address that it covers,
+//! inclusive. ipv4 addresses are stored as a `u32` and ipv6 addresses as
+//! a `u128`. The intervals of each address family are sorted, do not overlap,
+//! and are not adjacent to each other - adjacent ranges are combined into a
+//! single interval even when they can't be merged into a single CIDR range.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
+mod lookup_table;
 mod merge;
 mod range;
 mod range_interest;
 #[cfg(feature = "std")]
 mod std_range;
 
+#[cfg(feature = "std")]
+pub use lookup_table::{write_lookup_table, LookupTable, LookupTableError};
 pub use merge::merge_ranges_slice;
 pub use range::Range;
 pub use range_interest::RangeInterest;
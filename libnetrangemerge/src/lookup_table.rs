@@ -0,0 +1,412 @@
+//! A compact binary format for checking if an address is contained
+//! in a set of ranges. The format is described in the "Lookup Tables"
+//! section of the crate documentation.
+
+use crate::{IpRange, Range};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const MAGIC: &[u8; 4] = b"NRLT";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 32;
+const IPV4_INTERVAL_SIZE: usize = 8;
+const IPV6_INTERVAL_SIZE: usize = 32;
+
+/// An error indicating why a lookup table could not be read.
+pub enum LookupTableError {
+    /// The data doesn't start with the expected magic bytes.
+    InvalidMagic,
+    /// The data is in a version of the format that isn't supported.
+    UnsupportedVersion(u16),
+    /// The header has a non-zero value in bytes that are reserved, which
+    /// are only used by a later revision of the format.
+    ReservedNotZero,
+    /// The data is shorter than the header says that it should be.
+    Truncated,
+    /// The data doesn't match the checksum in the header.
+    ChecksumMismatch,
+}
+
+impl Debug for LookupTableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupTableError::InvalidMagic => write!(f, "Data is not a lookup table"),
+            LookupTableError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported lookup table version: {}", version)
+            }
+            LookupTableError::ReservedNotZero => {
+                write!(f, "Lookup table header has non-zero reserved bytes")
+            }
+            LookupTableError::Truncated => write!(f, "Lookup table is truncated"),
+            LookupTableError::ChecksumMismatch => {
+                write!(f, "Lookup table does not match its checksum")
+            }
+        }
+    }
+}
+
+impl Display for LookupTableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl Error for LookupTableError {}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Return the offset of the ipv6 table, or `None` if it doesn't fit in a
+/// `usize`.
+fn ipv6_table_offset(ipv4_count: usize) -> Option<usize> {
+    // The end of the ipv4 table is always a multiple of 8, so
+    // it either is already aligned or needs 8 bytes of padding
+    let end_of_ipv4 = ipv4_count
+        .checked_mul(IPV4_INTERVAL_SIZE)?
+        .checked_add(HEADER_SIZE)?;
+    end_of_ipv4.checked_add(end_of_ipv4 % 16)
+}
+
+/// Sort `intervals` and combine any that overlap or are adjacent.
+fn combine_intervals<T: Copy + Ord>(intervals: &mut Vec<(T, T)>, next: impl Fn(T) -> Option<T>) {
+    intervals.sort_unstable();
+    let mut combined: Vec<(T, T)> = Vec::with_capacity(intervals.len());
+    for (first, last) in intervals.drain(..) {
+        if let Some(prev) = combined.last_mut() {
+            let touches = match next(prev.1) {
+                Some(n) => first <= n,
+                None => true,
+            };
+            if touches {
+                if last > prev.1 {
+                    prev.1 = last;
+                }
+                continue;
+            }
+        }
+        combined.push((first, last));
+    }
+    *intervals = combined;
+}
+
+/// Write a lookup table containing `ranges` to `out`.
+///
+/// The ranges don't need to be sorted or merged beforehand.
+///
+/// # Example
+///
+/// ```
+/// use libnetrangemerge::{write_lookup_table, IpRange, LookupTable};
+///
+/// let ranges: Vec<IpRange> = vec![
+///     "127.0.0.0/8".parse().unwrap(),
+///     "2600::/16".parse().unwrap(),
+/// ];
+///
+/// let mut data = Vec::new();
+/// write_lookup_table(&ranges, &mut data).unwrap();
+///
+/// let table = LookupTable::from_bytes(&data).unwrap();
+/// assert!(table.contains("127.0.0.1".parse().unwrap()));
+/// ```
+pub fn write_lookup_table<W: Write>(ranges: &[IpRange], mut out: W) -> io::Result<()> {
+    let mut ipv4_intervals = Vec::new();
+    let mut ipv6_intervals = Vec::new();
+    for range in ranges {
+        match range.host_address() {
+            IpAddr::V4(addr) => {
+                let first = u32::from(*addr);
+                let host_mask = u32::MAX
+                    .checked_shr(range.prefix_length() as u32)
+                    .unwrap_or(0);
+                ipv4_intervals.push((first, first | host_mask));
+            }
+            IpAddr::V6(addr) => {
+                let first = u128::from(*addr);
+                let host_mask = u128::MAX
+                    .checked_shr(range.prefix_length() as u32)
+                    .unwrap_or(0);
+                ipv6_intervals.push((first, first | host_mask));
+            }
+        }
+    }
+    combine_intervals(&mut ipv4_intervals, |a| a.checked_add(1));
+    combine_intervals(&mut ipv6_intervals, |a| a.checked_add(1));
+
+    // The intervals are already in memory, so the size of their table fits
+    let ipv6_offset = ipv6_table_offset(ipv4_intervals.len()).unwrap();
+    let mut body =
+        Vec::with_capacity(ipv6_offset - HEADER_SIZE + ipv6_intervals.len() * IPV6_INTERVAL_SIZE);
+    for (first, last) in &ipv4_intervals {
+        body.extend_from_slice(&first.to_le_bytes());
+        body.extend_from_slice(&last.to_le_bytes());
+    }
+    body.resize(ipv6_offset - HEADER_SIZE, 0);
+    for (first, last) in &ipv6_intervals {
+        body.extend_from_slice(&first.to_le_bytes());
+        body.extend_from_slice(&last.to_le_bytes());
+    }
+
+    let mut header = [0u8; HEADER_SIZE];
+    header[0..4].copy_from_slice(MAGIC);
+    header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..12].copy_from_slice(&(ipv4_intervals.len() as u32).to_le_bytes());
+    header[12..16].copy_from_slice(&(ipv6_intervals.len() as u32).to_le_bytes());
+    header[16..20].copy_from_slice(&crc32(&body).to_le_bytes());
+
+    out.write_all(&header)?;
+    out.write_all(&body)?;
+    Ok(())
+}
+
+/// A `LookupTable` answers whether an address is contained in a set of
+/// ranges that were written with [`write_lookup_table`].
+///
+/// A `LookupTable` borrows the underlying data and never copies it,
+/// so it can be used directly on a memory mapped file. Each lookup
+/// is a binary search over the intervals of the address's family.
+#[derive(Copy, Clone)]
+pub struct LookupTable<'a> {
+    ipv4: &'a [u8],
+    ipv6: &'a [u8],
+}
+
+impl<'a> LookupTable<'a> {
+    /// Create a `LookupTable` over `data` after validating its header
+    /// and checksum.
+    pub fn from_bytes(data: &'a [u8]) -> Result<LookupTable<'a>, LookupTableError> {
+        if data.len() < HEADER_SIZE {
+            return if data.len() >= 4 && &data[0..4] != MAGIC {
+                Err(LookupTableError::InvalidMagic)
+            } else {
+                Err(LookupTableError::Truncated)
+            };
+        }
+        if &data[0..4] != MAGIC {
+            return Err(LookupTableError::InvalidMagic);
+        }
+        let version = u16::from_le_bytes(data[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(LookupTableError::UnsupportedVersion(version));
+        }
+        if data[6..8]
+            .iter()
+            .chain(&data[20..HEADER_SIZE])
+            .any(|b| *b != 0)
+        {
+            return Err(LookupTableError::ReservedNotZero);
+        }
+        let ipv4_count = u32::from_le_bytes(data[8..12].try_into().unwrap()) as usize;
+        let ipv6_count = u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(data[16..20].try_into().unwrap());
+
+        let ipv6_offset = ipv6_table_offset(ipv4_count).ok_or(LookupTableError::Truncated)?;
+        let end = ipv6_count
+            .checked_mul(IPV6_INTERVAL_SIZE)
+            .and_then(|size| size.checked_add(ipv6_offset))
+            .ok_or(LookupTableError::Truncated)?;
+        if data.len() < end {
+            return Err(LookupTableError::Truncated);
+        }
+        if crc32(&data[HEADER_SIZE..end]) != checksum {
+            return Err(LookupTableError::ChecksumMismatch);
+        }
+
+        Ok(LookupTable {
+            ipv4: &data[HEADER_SIZE..HEADER_SIZE + ipv4_count * IPV4_INTERVAL_SIZE],
+            ipv6: &data[ipv6_offset..end],
+        })
+    }
+
+    /// Return the number of ipv4 intervals in the table.
+    pub fn ipv4_len(&self) -> usize {
+        self.ipv4.len() / IPV4_INTERVAL_SIZE
+    }
+
+    /// Return the number of ipv6 intervals in the table.
+    pub fn ipv6_len(&self) -> usize {
+        self.ipv6.len() / IPV6_INTERVAL_SIZE
+    }
+
+    /// Return `true` if `addr` is contained in one of the ranges.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(addr) => self.contains_ipv4(addr),
+            IpAddr::V6(addr) => self.contains_ipv6(addr),
+        }
+    }
+
+    /// Return `true` if `addr` is contained in one of the ipv4 ranges.
+    pub fn contains_ipv4(&self, addr: Ipv4Addr) -> bool {
+        let addr = u32::from(addr);
+        let interval = |idx: usize| {
+            let entry = &self.ipv4[idx * IPV4_INTERVAL_SIZE..][..IPV4_INTERVAL_SIZE];
+            (
+                u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+            )
+        };
+        search(self.ipv4_len(), addr, interval)
+    }
+
+    /// Return `true` if `addr` is contained in one of the ipv6 ranges.
+    pub fn contains_ipv6(&self, addr: Ipv6Addr) -> bool {
+        let addr = u128::from(addr);
+        let interval = |idx: usize| {
+            let entry = &self.ipv6[idx * IPV6_INTERVAL_SIZE..][..IPV6_INTERVAL_SIZE];
+            (
+                u128::from_le_bytes(entry[0..16].try_into().unwrap()),
+                u128::from_le_bytes(entry[16..32].try_into().unwrap()),
+            )
+        };
+        search(self.ipv6_len(), addr, interval)
+    }
+}
+
+/// Binary search for the interval with the largest first address that
+/// isn't larger than `addr` and check if that interval contains `addr`.
+fn search<T: Ord>(len: usize, addr: T, interval: impl Fn(usize) -> (T, T)) -> bool {
+    let mut low = 0;
+    let mut high = len;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if interval(mid).0 <= addr {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low > 0 && addr <= interval(low - 1).1
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lookup_table::{write_lookup_table, LookupTable, LookupTableError};
+    use crate::IpRange;
+
+    fn build_table(ranges: &[&str]) -> Vec<u8> {
+        let ranges: Vec<IpRange> = ranges.iter().map(|r| r.parse().unwrap()).collect();
+        let mut data = Vec::new();
+        write_lookup_table(&ranges, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_contains() {
+        let data = build_table(&[
+            "127.0.0.8/29",
+            "10.0.0.0/8",
+            "127.0.0.16/29",
+            "10.1.0.0/16",
+            "0.0.0.0/32",
+            "255.255.255.255/32",
+            "2600::/16",
+            "::/128",
+        ]);
+        let table = LookupTable::from_bytes(&data).unwrap();
+
+        // 10.1.0.0/16 is covered by 10.0.0.0/8 and the two 127 ranges are adjacent
+        assert_eq!(table.ipv4_len(), 4);
+        assert_eq!(table.ipv6_len(), 2);
+
+        for addr in &[
+            "10.0.0.0",
+            "10.255.255.255",
+            "127.0.0.8",
+            "127.0.0.23",
+            "0.0.0.0",
+            "255.255.255.255",
+            "2600::",
+            "2600:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+            "::",
+        ] {
+            assert!(table.contains(addr.parse().unwrap()), "{}", addr);
+        }
+        for addr in &[
+            "9.255.255.255",
+            "11.0.0.0",
+            "127.0.0.7",
+            "127.0.0.24",
+            "0.0.0.1",
+            "255.255.255.254",
+            "2601::",
+            "::1",
+        ] {
+            assert!(!table.contains(addr.parse().unwrap()), "{}", addr);
+        }
+    }
+
+    #[test]
+    fn test_full_ranges() {
+        let data = build_table(&["0.0.0.0/0", "::/0"]);
+        let table = LookupTable::from_bytes(&data).unwrap();
+        assert!(table.contains("0.0.0.0".parse().unwrap()));
+        assert!(table.contains("255.255.255.255".parse().unwrap()));
+        assert!(table.contains("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_empty() {
+        let data = build_table(&[]);
+        let table = LookupTable::from_bytes(&data).unwrap();
+        assert!(!table.contains("127.0.0.1".parse().unwrap()));
+        assert!(!table.contains("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        let data = build_table(&["127.0.0.0/8", "2600::/16"]);
+
+        match LookupTable::from_bytes(b"not a lookup table at all, really") {
+            Err(LookupTableError::InvalidMagic) => {}
+            _ => panic!("Expected InvalidMagic failure"),
+        }
+
+        match LookupTable::from_bytes(&data[..data.len() - 1]) {
+            Err(LookupTableError::Truncated) => {}
+            _ => panic!("Expected Truncated failure"),
+        }
+
+        let mut corrupted = data.clone();
+        corrupted[40] ^= 1;
+        match LookupTable::from_bytes(&corrupted) {
+            Err(LookupTableError::ChecksumMismatch) => {}
+            _ => panic!("Expected ChecksumMismatch failure"),
+        }
+
+        let mut future = data.clone();
+        future[4] = 2;
+        match LookupTable::from_bytes(&future) {
+            Err(LookupTableError::UnsupportedVersion(2)) => {}
+            _ => panic!("Expected UnsupportedVersion failure"),
+        }
+
+        for offset in &[6, 7, 20, 31] {
+            let mut reserved = data.clone();
+            reserved[*offset] = 1;
+            match LookupTable::from_bytes(&reserved) {
+                Err(LookupTableError::ReservedNotZero) => {}
+                _ => panic!("Expected ReservedNotZero failure"),
+            }
+        }
+
+        let mut huge = data;
+        huge[8..16].copy_from_slice(&[0xff; 8]);
+        match LookupTable::from_bytes(&huge) {
+            Err(LookupTableError::Truncated) => {}
+            _ => panic!("Expected Truncated failure"),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::commands::lookup::{parse_address_or_range, read_queries};
+use crate::commands::ExitStatus;
+use crate::utils::address_count::{count_covered, count_union, range_bounds};
+use crate::utils::cloud_process_ranges::cloud_merged_ranges;
+use crate::utils::load_ranges::{
+    fetch_and_load_cloud_ranges, load_cloud_ranges, read_single_line_ranges,
+};
+use crate::CheckOptions;
+use anyhow::{bail, Error};
+use libnetrangemerge::IpRange;
+use std::fs::File;
+
+/// Load the ranges to check against, either from a file of ranges or
+/// from a cloud service after filtering, selecting, and merging them.
+fn load_check_ranges(options: CheckOptions) -> Result<Vec<IpRange>, Error> {
+    if let Some(ranges_file) = options.ranges_file {
+        let mut ranges = Vec::new();
+        read_single_line_ranges(&mut File::open(&ranges_file)?, &mut ranges, true)?;
+        Ok(ranges.into_iter().map(|r| *r.range()).collect())
+    } else if let Some(service) = options.service {
+        let ranges = match options.service_file {
+            Some(service_file) => load_cloud_ranges(&service, service_file)?,
+            None => fetch_and_load_cloud_ranges(&service, &options.cache)?,
+        };
+        let ranges = cloud_merged_ranges(
+            ranges,
+            options.filter,
+            options.filter_file,
+            options.select,
+            options.select_file,
+        )?;
+        Ok(ranges.into_iter().map(|r| r.range).collect())
+    } else {
+        bail!("Either --ranges-file or --service must be given");
+    }
+}
+
+pub fn check_command(options: CheckOptions) -> Result<ExitStatus, Error> {
+    let queries = read_queries(&options.queries)?
+        .iter()
+        .map(|q| Ok((q.clone(), parse_address_or_range(q)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut ipv4_intervals = Vec::new();
+    let mut ipv6_intervals = Vec::new();
+    for range in load_check_ranges(options)? {
+        let (is_ipv6, first, last) = range_bounds(&range);
+        if is_ipv6 {
+            ipv6_intervals.push((first, last));
+        } else {
+            ipv4_intervals.push((first, last));
+        }
+    }
+
+    let mut all_covered = true;
+    for (query, range) in &queries {
+        let (is_ipv6, first, last) = range_bounds(range);
+        let intervals = if is_ipv6 {
+            &ipv6_intervals
+        } else {
+            &ipv4_intervals
+        };
+        let size = count_union(vec![(first, last)]);
+        let covered = count_covered(first, last, intervals);
+        if covered == size {
+            println!("{} covered", query);
+        } else if covered == Default::default() {
+            all_covered = false;
+            println!("{} not-covered", query);
+        } else {
+            all_covered = false;
+            println!(
+                "{} partially-covered ({} of {} addresses)",
+                query, covered, size
+            );
+        }
+    }
+
+    if all_covered {
+        Ok(ExitStatus::Success)
+    } else {
+        Ok(ExitStatus::NotCovered)
+    }
+}
@@ -1,93 +1,368 @@
-use crate::utils::cloud_config::get_cloud_config;
-use crate::utils::cloud_process_ranges::cloud_process_ranges;
-use crate::utils::load_ranges::{fetch_and_load_cloud_ranges, load_cloud_ranges};
+use crate::commands::ExitStatus;
+use crate::utils::archive::Archive;
+use crate::utils::attributes::{attributes_to_json, attributes_to_text, collect_attributes};
+use crate::utils::cloud_config::{get_cloud_config, get_cloud_names};
+use crate::utils::cloud_process_ranges::{
+    cloud_merged_ranges, cloud_process_ranges, get_program, ProcessOptions,
+};
+use crate::utils::diff_ranges::{count_addresses, diff_records, format_delta, SortedMetadata};
+use crate::utils::fetch::{fetch_cloud_ranges, mirror_file_path, write_file_atomic};
+use crate::utils::filter_select::{filter_select, CloudRanges};
+use crate::utils::load_ranges::{
+    fetch_and_load_cloud_ranges, fetch_and_load_services_ranges, load_cloud_ranges,
+    load_services_ranges, parse_service_file,
+};
+use crate::utils::range_stats::RangeStats;
+use crate::utils::timestamp::format_timestamp;
 use crate::{
-    CloudFilterHelpOptions, CloudGetMergeOptions, CloudGetOptions, CloudGetReadOptions,
-    CloudMergeOptions, CloudReadOptions,
+    CacheOptions, CloudAttributesOptions, CloudDiffOptions, CloudFilterHelpOptions,
+    CloudGetMergeOptions, CloudGetOptions, CloudGetReadOptions, CloudHistoryOptions,
+    CloudMergeOptions, CloudMirrorPopulateOptions, CloudReadOptions, CloudStatsOptions,
 };
-use anyhow::Error;
-use std::io;
+use anyhow::{anyhow, bail, Error};
+use libnetrangemerge::{IpRange, Range as _};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
 
 pub fn cloud_get_command(options: CloudGetOptions) -> Result<(), Error> {
-    let func = get_cloud_config(&options.service)?.fetch_ranges_func;
-    let mut response = func()?;
-    io::copy(&mut response, &mut io::stdout().lock())?;
+    let body = fetch_cloud_ranges(&options.service, &options.cache)?;
+    io::stdout().lock().write_all(&body)?;
     Ok(())
 }
 
+/// Load the ranges of the service and file arguments and of each
+/// "--file <service>=<path>" option, returning them along with the names of
+/// the services that they came from.
+fn load_service_files(
+    service: Option<String>,
+    file: Option<PathBuf>,
+    service_files: &[String],
+    max_source_age: Option<u64>,
+) -> Result<(String, CloudRanges), Error> {
+    let mut files = Vec::new();
+    match (service, file) {
+        (Some(service), Some(file)) => files.push((service, file)),
+        (Some(service), None) => bail!("No file given to load {}'s ranges from", service),
+        _ => {}
+    }
+    for service_file in service_files {
+        files.push(parse_service_file(service_file)?);
+    }
+    let mut services: Vec<&str> = Vec::new();
+    for (service, _) in &files {
+        if !services.contains(&service.as_str()) {
+            services.push(service);
+        }
+    }
+    let services = services.join(",");
+    Ok((services, load_services_ranges(files, max_source_age)?))
+}
+
 pub fn cloud_merge_command(options: CloudMergeOptions) -> Result<(), Error> {
-    let ranges = load_cloud_ranges(&options.service, options.file)?;
+    let (services, ranges) = load_service_files(
+        options.service,
+        options.file,
+        &options.files,
+        options.max_source_age,
+    )?;
 
     cloud_process_ranges(
+        &services,
         ranges,
-        options.filter,
-        options.filter_file,
-        options.select,
-        options.select_file,
-        options.extra_ranges_files,
-        options.min_ipv4_network_size,
-        options.min_ipv6_network_size,
-        true,
+        ProcessOptions {
+            filter_program: options.filter,
+            filter_file: options.filter_file,
+            select_program: options.select,
+            select_file: options.select_file,
+            extra_ranges_files: options.extra_ranges_files,
+            min_ipv4_network_size: options.min_ipv4_network_size,
+            min_ipv6_network_size: options.min_ipv6_network_size,
+            merge: true,
+            include_unselected: options.include_unselected,
+            group_by: options.group_by,
+            output_dir: options.output_dir,
+            report: Some(options.report),
+        },
+        &options.output,
     )?;
 
     Ok(())
 }
 
 pub fn cloud_get_merge_command(options: CloudGetMergeOptions) -> Result<(), Error> {
-    let ranges = fetch_and_load_cloud_ranges(&options.service)?;
+    let ranges =
+        fetch_and_load_services_ranges(&options.services, &options.cache, options.max_source_age)?;
 
     cloud_process_ranges(
+        &options.services.join(","),
         ranges,
-        options.filter,
-        options.filter_file,
-        options.select,
-        options.select_file,
-        options.extra_ranges_files,
-        options.min_ipv4_network_size,
-        options.min_ipv6_network_size,
-        true,
+        ProcessOptions {
+            filter_program: options.filter,
+            filter_file: options.filter_file,
+            select_program: options.select,
+            select_file: options.select_file,
+            extra_ranges_files: options.extra_ranges_files,
+            min_ipv4_network_size: options.min_ipv4_network_size,
+            min_ipv6_network_size: options.min_ipv6_network_size,
+            merge: true,
+            include_unselected: options.include_unselected,
+            group_by: options.group_by,
+            output_dir: options.output_dir,
+            report: Some(options.report),
+        },
+        &options.output,
     )?;
 
     Ok(())
 }
 
 pub fn cloud_read_command(options: CloudReadOptions) -> Result<(), Error> {
-    let ranges = load_cloud_ranges(&options.service, options.file)?;
+    let (services, ranges) = load_service_files(
+        options.service,
+        options.file,
+        &options.files,
+        options.max_source_age,
+    )?;
 
     cloud_process_ranges(
+        &services,
         ranges,
-        options.filter,
-        options.filter_file,
-        None,
-        None,
-        vec![],
-        None,
-        None,
-        false,
+        ProcessOptions {
+            filter_program: options.filter,
+            filter_file: options.filter_file,
+            ..Default::default()
+        },
+        &options.output,
     )?;
 
     Ok(())
 }
 
 pub fn cloud_get_read_command(options: CloudGetReadOptions) -> Result<(), Error> {
-    let ranges = fetch_and_load_cloud_ranges(&options.service)?;
+    let ranges =
+        fetch_and_load_services_ranges(&options.services, &options.cache, options.max_source_age)?;
 
     cloud_process_ranges(
+        &options.services.join(","),
         ranges,
-        options.filter,
-        options.filter_file,
-        None,
-        None,
-        vec![],
-        None,
-        None,
-        false,
+        ProcessOptions {
+            filter_program: options.filter,
+            filter_file: options.filter_file,
+            ..Default::default()
+        },
+        &options.output,
     )?;
 
     Ok(())
 }
 
 pub fn cloud_filter_help_command(options: CloudFilterHelpOptions) -> Result<(), Error> {
-    println!("{}", get_cloud_config(&options.service)?.filter_help.trim());
+    let cc = get_cloud_config(&options.service)?;
+    let help = match (options.file, cc.filter_help_func) {
+        (Some(file), Some(filter_help_func)) => {
+            if let Some("-") = file.as_path().to_str() {
+                filter_help_func(&mut io::stdin().lock())?
+            } else {
+                filter_help_func(&mut File::open(&file)?)?
+            }
+        }
+        _ => cc.filter_help.to_string(),
+    };
+    println!("{}", help.trim());
+    Ok(())
+}
+
+pub fn cloud_stats_command(options: CloudStatsOptions) -> Result<(), Error> {
+    let ranges = load_cloud_ranges(&options.service, options.file)?;
+    let ranges = filter_select(ranges, None, None)?;
+
+    let stats = RangeStats::new(&ranges, &options.attributes);
+    if options.format == "json" {
+        println!("{}", serde_json::to_string_pretty(&stats.to_json())?);
+    } else {
+        print!("{}", stats.to_table());
+    }
+    Ok(())
+}
+
+pub fn cloud_attributes_command(options: CloudAttributesOptions) -> Result<(), Error> {
+    let ranges = if let Some(file) = options.file {
+        load_cloud_ranges(&options.service, file)?
+    } else {
+        fetch_and_load_cloud_ranges(&options.service, &options.cache)?
+    };
+    let ranges = filter_select(ranges, None, None)?;
+
+    let attributes = collect_attributes(&ranges);
+    if options.format == "json" {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&attributes_to_json(&attributes))?
+        );
+    } else {
+        print!("{}", attributes_to_text(&attributes));
+    }
+    Ok(())
+}
+
+fn format_sorted_metadata(metadata: &SortedMetadata) -> String {
+    let values: Vec<String> = metadata
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    values.join(" ")
+}
+
+pub fn cloud_diff_command(options: CloudDiffOptions) -> Result<ExitStatus, Error> {
+    let old_ranges = load_cloud_ranges(&options.service, options.old_file)?;
+    let new_ranges = load_cloud_ranges(&options.service, options.new_file)?;
+
+    let diff = diff_records(old_ranges.clone(), new_ranges.clone())?;
+    for (range, metadata) in &diff.added {
+        println!("added    {} {}", range, format_sorted_metadata(metadata));
+    }
+    for (range, metadata) in &diff.removed {
+        println!("removed  {} {}", range, format_sorted_metadata(metadata));
+    }
+    for (range, removed, added) in &diff.changed {
+        println!("changed  {}", range);
+        for metadata in removed {
+            println!("    - {}", format_sorted_metadata(metadata));
+        }
+        for metadata in added {
+            println!("    + {}", format_sorted_metadata(metadata));
+        }
+    }
+
+    let filter_program = get_program(options.filter, options.filter_file)?;
+    let select_program = get_program(options.select, options.select_file)?;
+    let merged = |ranges| -> Result<Vec<IpRange>, Error> {
+        Ok(cloud_merged_ranges(
+            ranges,
+            filter_program.clone(),
+            None,
+            select_program.clone(),
+            None,
+        )?
+        .into_iter()
+        .map(|r| r.range)
+        .collect())
+    };
+    let old_output = merged(old_ranges)?;
+    let new_output = merged(new_ranges)?;
+
+    let key = |r: &IpRange| (*r.host_address(), r.prefix_length());
+    let old_keys: HashSet<_> = old_output.iter().map(key).collect();
+    let new_keys: HashSet<_> = new_output.iter().map(key).collect();
+    let mut output_added: Vec<_> = new_output
+        .iter()
+        .filter(|r| !old_keys.contains(&key(r)))
+        .collect();
+    let mut output_removed: Vec<_> = old_output
+        .iter()
+        .filter(|r| !new_keys.contains(&key(r)))
+        .collect();
+    output_added.sort_by_key(|r| key(r));
+    output_removed.sort_by_key(|r| key(r));
+
+    if !diff.added.is_empty() || !diff.removed.is_empty() || !diff.changed.is_empty() {
+        println!();
+    }
+    for range in &output_added {
+        println!("output added    {}", range);
+    }
+    for range in &output_removed {
+        println!("output removed  {}", range);
+    }
+    let (old_ipv4, old_ipv6) = count_addresses(&old_output);
+    let (new_ipv4, new_ipv6) = count_addresses(&new_output);
+    println!(
+        "ipv4 addresses: {} -> {} ({})",
+        old_ipv4,
+        new_ipv4,
+        format_delta(old_ipv4, new_ipv4)
+    );
+    println!(
+        "ipv6 addresses: {} -> {} ({})",
+        old_ipv6,
+        new_ipv6,
+        format_delta(old_ipv6, new_ipv6)
+    );
+
+    if output_added.is_empty() && output_removed.is_empty() {
+        Ok(ExitStatus::Success)
+    } else {
+        Ok(ExitStatus::OutputChanged)
+    }
+}
+
+pub fn cloud_history_command(options: CloudHistoryOptions) -> Result<(), Error> {
+    let snapshots = Archive::new(&options.archive_dir).snapshots(&options.service)?;
+    if options.format == "json" {
+        let snapshots: Vec<_> = snapshots
+            .iter()
+            .map(|s| {
+                json!({
+                    "fetched_at": format_timestamp(s.fetched_at),
+                    "url": s.url,
+                    "created_at": s.created_at.map(format_timestamp),
+                    "sha256": s.sha256,
+                    "provider_metadata": s.provider_metadata,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&snapshots)?);
+    } else {
+        for snapshot in snapshots {
+            let metadata: Vec<String> = snapshot
+                .url
+                .iter()
+                .map(|url| format!("url={}", url))
+                .chain(
+                    snapshot
+                        .provider_metadata
+                        .iter()
+                        .map(|(key, value)| format!("{}={}", key, value)),
+                )
+                .collect();
+            println!(
+                "{} {} {}",
+                format_timestamp(snapshot.fetched_at),
+                snapshot.sha256,
+                metadata.join(" ")
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn cloud_mirror_populate_command(options: CloudMirrorPopulateOptions) -> Result<(), Error> {
+    let mirror_dir = options
+        .cache
+        .mirror_dir
+        .clone()
+        .ok_or_else(|| anyhow!("The mirror directory must be given with --mirror-dir"))?;
+    let fetch_options = CacheOptions {
+        mirror_dir: None,
+        ..options.cache
+    };
+    let services: Vec<String> = if options.services.is_empty() {
+        get_cloud_names().iter().map(|s| s.to_string()).collect()
+    } else {
+        options.services
+    };
+
+    fs::create_dir_all(&mirror_dir)?;
+    for service in services {
+        let body = fetch_cloud_ranges(&service, &fetch_options)?;
+        (get_cloud_config(&service)?.load_ranges_func)(&mut body.as_slice())
+            .map_err(|e| anyhow!("Invalid range file for {}: {}", service, e))?;
+        let path = mirror_file_path(&mirror_dir, &service)?;
+        write_file_atomic(&path, &body)?;
+        println!("{}", path.display());
+    }
     Ok(())
 }
@@ -0,0 +1,216 @@
+use crate::utils::cloud_config::get_cloud_names;
+use crate::utils::filter_select::{filter_select, RangeWithMetadata};
+use crate::utils::load_ranges::{
+    fetch_and_load_cloud_ranges, load_cloud_ranges, parse_service_file,
+};
+use crate::LookupOptions;
+use anyhow::{anyhow, bail, Error};
+use libnetrangemerge::{IpRange, Range as _};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+use std::net::IpAddr;
+
+/// Parse a single IP address or a CIDR range. Addresses are
+/// treated as a range containing only that address.
+pub fn parse_address_or_range(value: &str) -> Result<IpRange, Error> {
+    let value = value.trim();
+    if value.contains('/') {
+        Ok(value.parse()?)
+    } else {
+        let addr: IpAddr = value
+            .parse()
+            .map_err(|_| anyhow!("Invalid IP address or range: {}", value))?;
+        let prefix_length = if addr.is_ipv6() { 128 } else { 32 };
+        Ok(IpRange::new(addr, prefix_length)?)
+    }
+}
+
+/// Read the values from the command line, or from STDIN, one per line, if
+/// there are none or if one of them is "-".
+pub fn read_queries(values: &[String]) -> Result<Vec<String>, Error> {
+    let mut queries = Vec::new();
+    let read_stdin = values.is_empty() || values.iter().any(|v| v == "-");
+    for value in values.iter().filter(|v| *v != "-") {
+        queries.push(value.clone());
+    }
+    if read_stdin {
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                queries.push(line.trim().to_string());
+            }
+        }
+    }
+    Ok(queries)
+}
+
+/// `true` if `outer` contains every address in `inner`.
+pub fn range_contains(outer: &IpRange, inner: &IpRange) -> bool {
+    outer.is_ipv6() == inner.is_ipv6()
+        && outer.prefix_length() <= inner.prefix_length()
+        && outer.contains(inner)
+}
+
+struct Match<'a> {
+    service: &'a str,
+    record: &'a RangeWithMetadata,
+    contains_query: bool,
+    most_specific: bool,
+}
+
+/// Load the ranges of every service to look in, from the files given
+/// with `--file` or else by downloading them. The services looked in are
+/// those given with `--service` or `--file`, any of which failing to load
+/// is an error. If there are none, every service is looked in and those
+/// that fail to load are skipped with a warning so that the others can
+/// still be checked, as long as at least one of them loads.
+fn load_services(options: &LookupOptions) -> Result<Vec<(String, Vec<RangeWithMetadata>)>, Error> {
+    let mut files = BTreeMap::new();
+    for file in &options.files {
+        let (service, path) = parse_service_file(file)?;
+        files.insert(service, path);
+    }
+
+    let mut services: Vec<String> = Vec::new();
+    let all_services = options.services.is_empty() && files.is_empty();
+    for service in options.services.iter().chain(files.keys()) {
+        if !services.contains(service) {
+            services.push(service.clone());
+        }
+    }
+    if all_services {
+        services = get_cloud_names().iter().map(|s| s.to_string()).collect();
+    }
+
+    let mut loaded = Vec::new();
+    for service in services {
+        let ranges = match files.get(&service) {
+            Some(path) => load_cloud_ranges(&service, path.clone()),
+            None => fetch_and_load_cloud_ranges(&service, &options.cache),
+        };
+        match ranges.and_then(|r| filter_select(r, None, None)) {
+            Ok(ranges) => loaded.push((service, ranges)),
+            Err(err) if all_services => eprintln!("Warning: Skipping {}: {}", service, err),
+            Err(err) => bail!("Unable to load the ranges of {}: {}", service, err),
+        }
+    }
+    if loaded.is_empty() {
+        bail!("Unable to load the ranges of any service");
+    }
+    Ok(loaded)
+}
+
+fn find_matches<'a>(
+    query: &IpRange,
+    services: &'a [(String, Vec<RangeWithMetadata>)],
+) -> Vec<Match<'a>> {
+    let mut matches: Vec<Match> = services
+        .iter()
+        .flat_map(|(service, ranges)| {
+            ranges.iter().filter_map(move |record| {
+                let range = record.range.range();
+                let contains_query = range_contains(range, query);
+                if contains_query || range_contains(query, range) {
+                    Some(Match {
+                        service,
+                        record,
+                        contains_query,
+                        most_specific: false,
+                    })
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+
+    let most_specific_length = matches
+        .iter()
+        .filter(|m| m.contains_query)
+        .map(|m| m.record.range.range().prefix_length())
+        .max();
+    for m in matches.iter_mut() {
+        m.most_specific = m.contains_query
+            && Some(m.record.range.range().prefix_length()) == most_specific_length;
+    }
+    matches.sort_by_key(|m| {
+        (
+            !m.contains_query,
+            std::cmp::Reverse(m.record.range.range().prefix_length()),
+        )
+    });
+    matches
+}
+
+fn format_metadata(record: &RangeWithMetadata) -> String {
+    let metadata: BTreeMap<_, _> = record.metadata.iter().collect();
+    let values: Vec<String> = metadata
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    values.join(" ")
+}
+
+fn match_to_json(m: &Match) -> Value {
+    let metadata: Map<String, Value> = m
+        .record
+        .metadata
+        .iter()
+        .map(|(key, value)| (key.to_string(), json!(value)))
+        .collect();
+    json!({
+        "service": m.service,
+        "range": m.record.range.range().to_string(),
+        "contains_query": m.contains_query,
+        "most_specific": m.most_specific,
+        "metadata": metadata,
+    })
+}
+
+pub fn lookup_command(options: LookupOptions) -> Result<(), Error> {
+    let queries = read_queries(&options.queries)?
+        .iter()
+        .map(|q| Ok((q.clone(), parse_address_or_range(q)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let services = load_services(&options)?;
+
+    if options.format == "json" {
+        let results: Vec<Value> = queries
+            .iter()
+            .map(|(query, range)| {
+                let matches: Vec<Value> = find_matches(range, &services)
+                    .iter()
+                    .map(match_to_json)
+                    .collect();
+                json!({"query": query, "matches": matches})
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        for (query, range) in &queries {
+            println!("{}", query);
+            let matches = find_matches(range, &services);
+            if matches.is_empty() {
+                println!("    no matches");
+            }
+            for m in matches {
+                let relation = if m.most_specific {
+                    " (most specific)"
+                } else if !m.contains_query {
+                    " (within query)"
+                } else {
+                    ""
+                };
+                println!(
+                    "    {} {}{} {}",
+                    m.service,
+                    m.record.range.range(),
+                    relation,
+                    format_metadata(m.record)
+                );
+            }
+        }
+    }
+    Ok(())
+}
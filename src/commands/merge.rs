@@ -1,10 +1,13 @@
+use crate::formats::{OutputContext, OutputRange};
 use crate::utils::expand_ranges::expand_ranges;
 use crate::utils::load_ranges::read_single_line_ranges;
+use crate::utils::merge_report::handle_merge_report;
+use crate::utils::write_ranges::write_ranges;
 use crate::MergeOptions;
 use anyhow::Error;
 use libnetrangemerge::merge_ranges;
 use std::fs::File;
-use std::io::{self, Write as _};
+use std::io;
 
 pub fn merge_command(options: MergeOptions) -> Result<(), Error> {
     let mut ranges = Vec::new();
@@ -18,6 +21,8 @@ pub fn merge_command(options: MergeOptions) -> Result<(), Error> {
         read_single_line_ranges(&mut File::open(&extra_file)?, &mut ranges, false)?
     }
 
+    let input_ranges = ranges.clone();
+
     expand_ranges(
         ranges.iter_mut().map(|n| n.range_mut()),
         options.min_ipv4_network_size,
@@ -26,12 +31,16 @@ pub fn merge_command(options: MergeOptions) -> Result<(), Error> {
 
     merge_ranges(&mut ranges);
 
-    let stdout = io::stdout();
-    let mut stdout = io::BufWriter::new(stdout.lock());
-    for r in ranges {
-        writeln!(stdout, "{}", r.range())?;
-    }
-    stdout.flush()?;
+    let ranges: Vec<_> = ranges
+        .into_iter()
+        .map(|r| OutputRange::new(*r.range(), r.is_selected()))
+        .collect();
+    handle_merge_report(&options.report, &input_ranges, &ranges, None)?;
+    let context = OutputContext {
+        merged: true,
+        ..Default::default()
+    };
+    write_ranges(&ranges, &options.output, &context)?;
 
     Ok(())
 }
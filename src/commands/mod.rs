@@ -1,2 +1,27 @@
+pub mod check;
 pub mod cloud;
+pub mod lookup;
 pub mod merge;
+
+/// How the process should exit after a command that didn't fail. Commands
+/// that report a result through the exit code return this so that only
+/// `main` exits the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Everything went as expected.
+    Success,
+    /// Some of the checked addresses or ranges aren't fully covered.
+    NotCovered,
+    /// The merged output of the compared range files changed.
+    OutputChanged,
+}
+
+impl ExitStatus {
+    /// The exit code of the process.
+    pub fn code(self) -> i32 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::NotCovered | ExitStatus::OutputChanged => 2,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::formats::{Action, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use std::io::Write;
+
+/// Write the ranges as a `Require ip` authorization block. Allowed ranges
+/// are wrapped in `<RequireAny>` while denied ranges are wrapped in
+/// `<RequireAll>` along with a `Require all granted` directive so that
+/// all other addresses are still permitted.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    _context: &OutputContext,
+) -> Result<(), Error> {
+    match options.action {
+        Action::Allow => {
+            writeln!(out, "<RequireAny>")?;
+            for OutputRange { range, .. } in ranges {
+                writeln!(out, "    Require ip {}", range)?;
+            }
+            writeln!(out, "</RequireAny>")?;
+        }
+        Action::Deny => {
+            writeln!(out, "<RequireAll>")?;
+            writeln!(out, "    Require all granted")?;
+            for OutputRange { range, .. } in ranges {
+                writeln!(out, "    Require not ip {}", range)?;
+            }
+            writeln!(out, "</RequireAll>")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::apache::write_ranges;
+    use crate::formats::test_util::render;
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            render(write_ranges, RANGES, &[]),
+            "<RequireAny>\n    Require ip 10.0.0.0/24\n    Require ip 2001:db8::/32\n</RequireAny>\n"
+        );
+        assert_eq!(
+            render(write_ranges, RANGES, &["--action", "deny"]),
+            "<RequireAll>\n    Require all granted\n    Require not ip 10.0.0.0/24\n    \
+             Require not ip 2001:db8::/32\n</RequireAll>\n"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::formats::{prefix_length_bounds, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use libnetrangemerge::Range as _;
+use std::io::Write;
+
+/// Write the ranges as BIRD prefix set constants. BIRD prefix sets
+/// can't mix address families, so ipv4 ranges are defined as
+/// `<name>_v4` and ipv6 ranges as `<name>_v6`.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    // Bird identifiers can't contain a "-"
+    let name = context.name_with_separator(options, "_");
+    for (suffix, is_ipv6) in &[("v4", false), ("v6", true)] {
+        let family_ranges: Vec<_> = ranges
+            .iter()
+            .map(|r| &r.range)
+            .filter(|r| r.is_ipv6() == *is_ipv6)
+            .collect();
+        if family_ranges.is_empty() {
+            continue;
+        }
+        writeln!(out, "define {}_{} = [", name, suffix)?;
+        for (idx, range) in family_ranges.iter().enumerate() {
+            let pattern = match prefix_length_bounds(range, options)? {
+                (None, None) => String::new(),
+                (ge, le) => format!(
+                    "{{{},{}}}",
+                    ge.unwrap_or_else(|| range.prefix_length()),
+                    le.unwrap_or(if *is_ipv6 { 128 } else { 32 })
+                ),
+            };
+            let separator = if idx + 1 < family_ranges.len() {
+                ","
+            } else {
+                ""
+            };
+            writeln!(out, "    {}{}{}", range, pattern, separator)?;
+        }
+        writeln!(out, "];")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::bird::write_ranges;
+    use crate::formats::test_util::render;
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            render(
+                write_ranges,
+                &["10.0.0.0/24", "10.0.2.0/23", "2001:db8::/32"],
+                &["--name", "cloud", "--ipv4-le", "24"]
+            ),
+            "define cloud_v4 = [
+    10.0.0.0/24,
+    10.0.2.0/23{23,24}
+];
+define cloud_v6 = [
+    2001:db8::/32
+];
+"
+        );
+        // Families without ranges aren't defined
+        assert_eq!(
+            render(write_ranges, &["10.0.0.0/24"], &[]),
+            "define netrange_v4 = [\n    10.0.0.0/24\n];\n"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::formats::{prefix_length_bounds, Action, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::{anyhow, Error};
+use libnetrangemerge::{IpRange, Range as _};
+use std::io::Write;
+
+fn bounds_suffix(range: &IpRange, options: &OutputOptions) -> Result<String, Error> {
+    let (ge, le) = prefix_length_bounds(range, options)?;
+    let mut suffix = String::new();
+    if let Some(ge) = ge {
+        suffix.push_str(&format!(" ge {}", ge));
+    }
+    if let Some(le) = le {
+        suffix.push_str(&format!(" le {}", le));
+    }
+    Ok(suffix)
+}
+
+/// Write the ranges as `ip prefix-list` and `ipv6 prefix-list` entries.
+///
+/// FRR uses the same syntax as Cisco IOS, so this writer is used
+/// for both formats.
+pub fn write_ios_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let action = match options.action {
+        Action::Allow => "permit",
+        Action::Deny => "deny",
+    };
+    let name = context.name(options);
+    for (command, is_ipv6) in &[("ip", false), ("ipv6", true)] {
+        // `None` once the sequence numbers have run past the largest one
+        let mut next_seq = Some(options.seq_start);
+        for range in ranges
+            .iter()
+            .map(|r| &r.range)
+            .filter(|r| r.is_ipv6() == *is_ipv6)
+        {
+            let seq = next_seq.ok_or_else(|| {
+                anyhow!(
+                    "Too many {} prefix-list entries for sequence numbers starting \
+                     at {} with a step of {}",
+                    command,
+                    options.seq_start,
+                    options.seq_step
+                )
+            })?;
+            writeln!(
+                out,
+                "{} prefix-list {} seq {} {} {}{}",
+                command,
+                name,
+                seq,
+                action,
+                range,
+                bounds_suffix(range, options)?
+            )?;
+            next_seq = seq.checked_add(options.seq_step);
+        }
+    }
+    Ok(())
+}
+
+/// Write the ranges as an IOS-XR `prefix-set`.
+pub fn write_iosxr_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    writeln!(out, "prefix-set {}", context.name(options))?;
+    for (idx, OutputRange { range, .. }) in ranges.iter().enumerate() {
+        let separator = if idx + 1 < ranges.len() { "," } else { "" };
+        writeln!(
+            out,
+            "  {}{}{}",
+            range,
+            bounds_suffix(range, options)?,
+            separator
+        )?;
+    }
+    writeln!(out, "end-set")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::cisco::{write_ios_ranges, write_iosxr_ranges};
+    use crate::formats::test_util::{options, output_ranges, render};
+    use crate::formats::OutputContext;
+    use crate::utils::format_config::get_format_config;
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "10.0.2.0/23", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_ios_ranges() {
+        assert_eq!(
+            render(write_ios_ranges, RANGES, &["--name", "cloud"]),
+            "ip prefix-list cloud seq 5 permit 10.0.0.0/24
+ip prefix-list cloud seq 10 permit 10.0.2.0/23
+ipv6 prefix-list cloud seq 5 permit 2001:db8::/32
+"
+        );
+        assert_eq!(
+            render(
+                write_ios_ranges,
+                RANGES,
+                &[
+                    "--action",
+                    "deny",
+                    "--seq-start",
+                    "100",
+                    "--seq-step",
+                    "1",
+                    "--ipv4-le",
+                    "24",
+                    "--ipv6-ge",
+                    "48",
+                    "--ipv6-le",
+                    "64",
+                ]
+            ),
+            "ip prefix-list netrange seq 100 deny 10.0.0.0/24
+ip prefix-list netrange seq 101 deny 10.0.2.0/23 le 24
+ipv6 prefix-list netrange seq 100 deny 2001:db8::/32 ge 48 le 64
+"
+        );
+    }
+
+    #[test]
+    fn test_write_ios_ranges_seq_overflow() {
+        let args = &["--seq-start", "4294967290", "--seq-step", "10"];
+        let mut out = Vec::new();
+        assert!(write_ios_ranges(
+            &mut out,
+            &output_ranges(RANGES),
+            &options(args),
+            &OutputContext::default()
+        )
+        .is_err());
+
+        // The last sequence number may be the largest one
+        assert_eq!(
+            render(write_ios_ranges, &["10.0.0.0/24"], args),
+            "ip prefix-list netrange seq 4294967290 permit 10.0.0.0/24\n"
+        );
+    }
+
+    #[test]
+    fn test_write_frr_ranges() {
+        let frr = get_format_config("frr").unwrap().write_ranges_func;
+        assert_eq!(
+            render(
+                frr,
+                &["10.0.0.0/24", "2001:db8::/32"],
+                &["--seq-start", "10"]
+            ),
+            "ip prefix-list netrange seq 10 permit 10.0.0.0/24
+ipv6 prefix-list netrange seq 10 permit 2001:db8::/32
+"
+        );
+    }
+
+    #[test]
+    fn test_write_iosxr_ranges() {
+        assert_eq!(
+            render(
+                write_iosxr_ranges,
+                RANGES,
+                &["--ipv4-ge", "24", "--ipv4-le", "28"]
+            ),
+            "prefix-set netrange
+  10.0.0.0/24 le 28,
+  10.0.2.0/23 ge 24 le 28,
+  2001:db8::/32
+end-set
+"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::{bail, Error};
+use libnetrangemerge::Range as _;
+use serde_json::{json, Map};
+use std::io::Write;
+
+/// CloudFormation limits prefix list entry descriptions to 255 characters.
+const MAX_ENTRY_DESCRIPTION_LENGTH: usize = 255;
+
+/// Write the ranges as a CloudFormation template containing an
+/// `AWS::EC2::PrefixList` resource per address family. Families without any
+/// ranges don't get a resource.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let description = context.description(options);
+    let entry_description: String = description
+        .chars()
+        .take(MAX_ENTRY_DESCRIPTION_LENGTH)
+        .collect();
+    // Logical IDs may only contain alphanumeric characters
+    let name = context.name(options);
+    let logical_id: String = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+
+    let mut resources = Map::new();
+    for (family, is_ipv6) in &[("IPv4", false), ("IPv6", true)] {
+        let entries: Vec<_> = ranges
+            .iter()
+            .map(|r| &r.range)
+            .filter(|r| r.is_ipv6() == *is_ipv6)
+            .map(|r| {
+                json!({
+                    "Cidr": r.to_string(),
+                    "Description": entry_description,
+                })
+            })
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+        resources.insert(
+            format!("{}{}", logical_id, family),
+            json!({
+                "Type": "AWS::EC2::PrefixList",
+                "Properties": {
+                    "PrefixListName": format!("{}-{}", name, family.to_lowercase()),
+                    "AddressFamily": family,
+                    "MaxEntries": entries.len(),
+                    "Entries": entries,
+                }
+            }),
+        );
+    }
+
+    // CloudFormation rejects templates without any resources
+    if resources.is_empty() {
+        bail!("There are no ranges to write a CloudFormation template for");
+    }
+
+    let doc = json!({
+        "AWSTemplateFormatVersion": "2010-09-09",
+        "Description": description,
+        "Resources": resources,
+    });
+    serde_json::to_writer_pretty(&mut *out, &doc)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::cloudformation::write_ranges;
+    use crate::formats::test_util::{options, render_with_context};
+    use crate::formats::OutputContext;
+    use serde_json::{json, Value};
+
+    fn context() -> OutputContext {
+        OutputContext {
+            service: Some("aws".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn template(ranges: &[&str], args: &[&str]) -> Value {
+        let ranges = crate::formats::test_util::output_ranges(ranges);
+        serde_json::from_str(&render_with_context(
+            write_ranges,
+            &ranges,
+            args,
+            &context(),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            template(
+                &["10.0.0.0/24", "2001:db8::/32"],
+                &["--name", "cloud-ranges"]
+            ),
+            json!({
+                "AWSTemplateFormatVersion": "2010-09-09",
+                "Description": "IP ranges for aws",
+                "Resources": {
+                    "cloudrangesIPv4": {
+                        "Type": "AWS::EC2::PrefixList",
+                        "Properties": {
+                            "PrefixListName": "cloud-ranges-ipv4",
+                            "AddressFamily": "IPv4",
+                            "MaxEntries": 1,
+                            "Entries": [
+                                {"Cidr": "10.0.0.0/24", "Description": "IP ranges for aws"},
+                            ],
+                        },
+                    },
+                    "cloudrangesIPv6": {
+                        "Type": "AWS::EC2::PrefixList",
+                        "Properties": {
+                            "PrefixListName": "cloud-ranges-ipv6",
+                            "AddressFamily": "IPv6",
+                            "MaxEntries": 1,
+                            "Entries": [
+                                {"Cidr": "2001:db8::/32", "Description": "IP ranges for aws"},
+                            ],
+                        },
+                    },
+                },
+            })
+        );
+
+        let template = template(&["10.0.0.0/24"], &[]);
+        let resources = template["Resources"].as_object().unwrap();
+        assert_eq!(resources.keys().collect::<Vec<_>>(), vec!["netrangeIPv4"]);
+    }
+
+    #[test]
+    fn test_write_no_ranges() {
+        let mut out = Vec::new();
+        assert!(write_ranges(&mut out, &[], &options(&[]), &context()).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// Write the ranges as CSV with a column for each metadata key found
+/// in any of the ranges. If a range was merged from records with
+/// different values for a key, the values are separated by ";".
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    _options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let keys: BTreeSet<&'static str> = ranges
+        .iter()
+        .flat_map(|r| r.metadata.keys().copied())
+        .collect();
+
+    let mut writer = csv::Writer::from_writer(out);
+
+    let mut header = vec!["range"];
+    if context.merged {
+        header.push("selected");
+    }
+    header.extend(keys.iter());
+    writer.write_record(&header)?;
+
+    for range in ranges {
+        let mut record = vec![range.range.to_string()];
+        if context.merged {
+            record.push(range.selected.to_string());
+        }
+        for key in &keys {
+            let value = match range.metadata.get(key) {
+                Some(values) => values
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                None => String::new(),
+            };
+            record.push(value);
+        }
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::csv::write_ranges;
+    use crate::formats::test_util::{merged_ranges, render_with_context};
+    use crate::formats::OutputContext;
+
+    #[test]
+    fn test_write_ranges() {
+        let context = OutputContext {
+            merged: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_with_context(write_ranges, &merged_ranges(), &[], &context),
+            "range,selected,ipv6,region\n\
+             10.0.0.0/24,true,,us-east-1\n\
+             2001:db8::/32,false,true,eu-west-1;us-east-1\n"
+        );
+        assert_eq!(
+            render_with_context(
+                write_ranges,
+                &merged_ranges()[..1],
+                &[],
+                &OutputContext::default()
+            ),
+            "range,region\n10.0.0.0/24,us-east-1\n"
+        );
+    }
+}
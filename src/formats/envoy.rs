@@ -0,0 +1,74 @@
+use crate::formats::{Action, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use libnetrangemerge::Range as _;
+use std::io::Write;
+
+/// Write the ranges as the `principals` section of an Envoy RBAC policy.
+/// Denied ranges are wrapped in a `not_id` so that the policy matches
+/// every address outside of the ranges.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    _context: &OutputContext,
+) -> Result<(), Error> {
+    writeln!(out, "principals:")?;
+    let indent = match options.action {
+        Action::Allow => "",
+        Action::Deny => {
+            writeln!(out, "- not_id:")?;
+            writeln!(out, "    or_ids:")?;
+            writeln!(out, "      ids:")?;
+            "      "
+        }
+    };
+    for OutputRange { range, .. } in ranges {
+        writeln!(out, "{}- remote_ip:", indent)?;
+        writeln!(
+            out,
+            "{}    address_prefix: \"{}\"",
+            indent,
+            range.host_address()
+        )?;
+        writeln!(out, "{}    prefix_len: {}", indent, range.prefix_length())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::envoy::write_ranges;
+    use crate::formats::test_util::render;
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            render(write_ranges, RANGES, &[]),
+            r#"principals:
+- remote_ip:
+    address_prefix: "10.0.0.0"
+    prefix_len: 24
+- remote_ip:
+    address_prefix: "2001:db8::"
+    prefix_len: 32
+"#
+        );
+        assert_eq!(
+            render(write_ranges, RANGES, &["--action", "deny"]),
+            r#"principals:
+- not_id:
+    or_ids:
+      ids:
+      - remote_ip:
+          address_prefix: "10.0.0.0"
+          prefix_len: 24
+      - remote_ip:
+          address_prefix: "2001:db8::"
+          prefix_len: 32
+"#
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use crate::formats::{Action, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use std::io::Write;
+
+/// Write the ranges as an ACL file suitable for loading with
+/// `acl <name> src -f <file>`. ACL files can't express polarity
+/// themselves, so a comment describes the rule that should reference it.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let name = context.name(options);
+    writeln!(out, "# acl {} src -f <this file>", name)?;
+    match options.action {
+        Action::Allow => writeln!(out, "# http-request deny unless {}", name)?,
+        Action::Deny => writeln!(out, "# http-request deny if {}", name)?,
+    }
+    for OutputRange { range, .. } in ranges {
+        writeln!(out, "{}", range)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::haproxy::write_ranges;
+    use crate::formats::test_util::render;
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            render(write_ranges, RANGES, &["--name", "cloud"]),
+            "# acl cloud src -f <this file>\n# http-request deny unless cloud\n\
+             10.0.0.0/24\n2001:db8::/32\n"
+        );
+        assert_eq!(
+            render(write_ranges, RANGES, &["--action", "deny"]),
+            "# acl netrange src -f <this file>\n# http-request deny if netrange\n\
+             10.0.0.0/24\n2001:db8::/32\n"
+        );
+    }
+}
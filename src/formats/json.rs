@@ -0,0 +1,152 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use serde_json::{json, Map, Value};
+use std::io::Write;
+
+/// Build the JSON representation of the metadata of a range.
+///
+/// Ranges that were read have a single value for each metadata key
+/// while ranges that were merged have a list of all of the values of
+/// the records that were merged into it.
+pub fn metadata_value(range: &OutputRange, context: &OutputContext) -> Result<Value, Error> {
+    let mut metadata = Map::new();
+    for (key, values) in &range.metadata {
+        let value = if context.merged {
+            serde_json::to_value(values)?
+        } else {
+            serde_json::to_value(values.iter().next())?
+        };
+        metadata.insert(key.to_string(), value);
+    }
+    Ok(Value::Object(metadata))
+}
+
+/// Build the JSON representation of a single range.
+fn range_value(range: &OutputRange, context: &OutputContext) -> Result<Value, Error> {
+    let metadata = metadata_value(range, context)?;
+    let value = if context.merged {
+        json!({
+            "range": range.range.to_string(),
+            "selected": range.selected,
+            "metadata": metadata,
+        })
+    } else {
+        json!({
+            "range": range.range.to_string(),
+            "metadata": metadata,
+        })
+    };
+    Ok(value)
+}
+
+/// Write the ranges as a JSON array of objects. With `--source-header`, the
+/// array is written as the "ranges" field of an object that also has a
+/// "source" field with the service and the metadata of its range file.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let values = ranges
+        .iter()
+        .map(|r| range_value(r, context))
+        .collect::<Result<Vec<Value>, Error>>()?;
+    let doc = if options.source_header {
+        json!({
+            "source": {
+                "service": context.service,
+                "metadata": context.source_metadata,
+            },
+            "ranges": values,
+        })
+    } else {
+        Value::Array(values)
+    };
+    serde_json::to_writer_pretty(&mut *out, &doc)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write the ranges as newline delimited JSON with one object per line.
+pub fn write_ndjson_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    _options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    for range in ranges {
+        serde_json::to_writer(&mut *out, &range_value(range, context)?)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::json::{write_ndjson_ranges, write_ranges};
+    use crate::formats::test_util::{merged_ranges, render_with_context};
+    use crate::formats::OutputContext;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_write_ranges() {
+        let context = OutputContext {
+            merged: true,
+            ..Default::default()
+        };
+        let output = render_with_context(write_ranges, &merged_ranges(), &[], &context);
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!([
+                {
+                    "range": "10.0.0.0/24",
+                    "selected": true,
+                    "metadata": {"region": ["us-east-1"]},
+                },
+                {
+                    "range": "2001:db8::/32",
+                    "selected": false,
+                    "metadata": {"ipv6": [true], "region": ["eu-west-1", "us-east-1"]},
+                },
+            ])
+        );
+
+        let mut context = OutputContext {
+            service: Some("aws".to_string()),
+            ..Default::default()
+        };
+        context
+            .source_metadata
+            .insert("syncToken", "1".to_string().into());
+        let ranges = &merged_ranges()[..1];
+        let output = render_with_context(write_ranges, ranges, &["--source-header"], &context);
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!({
+                "source": {"service": "aws", "metadata": {"syncToken": "1"}},
+                "ranges": [{"range": "10.0.0.0/24", "metadata": {"region": "us-east-1"}}],
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_ndjson_ranges() {
+        let output = render_with_context(
+            write_ndjson_ranges,
+            &merged_ranges(),
+            &[],
+            &OutputContext::default(),
+        );
+        assert_eq!(
+            output,
+            concat!(
+                r#"{"metadata":{"region":"us-east-1"},"range":"10.0.0.0/24"}"#,
+                "\n",
+                r#"{"metadata":{"ipv6":true,"region":"eu-west-1"},"range":"2001:db8::/32"}"#,
+                "\n",
+            )
+        );
+    }
+}
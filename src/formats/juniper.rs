@@ -0,0 +1,71 @@
+use crate::formats::{prefix_length_bounds, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use libnetrangemerge::Range as _;
+use std::io::Write;
+
+/// Write the ranges as `policy-options` set commands.
+///
+/// A `prefix-list` can't express prefix length bounds, so if any bounds
+/// are configured a `route-filter-list` is written instead.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let has_bounds = options.ipv4_ge.is_some()
+        || options.ipv4_le.is_some()
+        || options.ipv6_ge.is_some()
+        || options.ipv6_le.is_some();
+    let name = context.name(options);
+    for OutputRange { range, .. } in ranges {
+        if !has_bounds {
+            writeln!(out, "set policy-options prefix-list {} {}", name, range)?;
+            continue;
+        }
+        let max_length = if range.is_ipv6() { 128 } else { 32 };
+        let modifier = match prefix_length_bounds(range, options)? {
+            (Some(ge), Some(le)) => format!("prefix-length-range /{}-/{}", ge, le),
+            (Some(ge), None) => format!("prefix-length-range /{}-/{}", ge, max_length),
+            (None, Some(le)) => format!("upto /{}", le),
+            (None, None) => "exact".to_string(),
+        };
+        writeln!(
+            out,
+            "set policy-options route-filter-list {} {} {}",
+            name, range, modifier
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::juniper::write_ranges;
+    use crate::formats::test_util::render;
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "10.0.2.0/23", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            render(write_ranges, RANGES, &["--name", "cloud"]),
+            "set policy-options prefix-list cloud 10.0.0.0/24
+set policy-options prefix-list cloud 10.0.2.0/23
+set policy-options prefix-list cloud 2001:db8::/32
+"
+        );
+        assert_eq!(
+            render(
+                write_ranges,
+                RANGES,
+                &["--ipv4-le", "24", "--ipv6-ge", "48"]
+            ),
+            "set policy-options route-filter-list netrange 10.0.0.0/24 exact
+set policy-options route-filter-list netrange 10.0.2.0/23 upto /24
+set policy-options route-filter-list netrange 2001:db8::/32 prefix-length-range /48-/128
+"
+        );
+    }
+}
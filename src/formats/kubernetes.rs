@@ -0,0 +1,127 @@
+use crate::formats::{Action, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use libnetrangemerge::Range as _;
+use std::io::Write;
+
+/// Write the ranges as a `NetworkPolicy` that controls egress from
+/// every pod in the namespace.
+///
+/// NetworkPolicies can only allow traffic, so denied ranges are written
+/// as exceptions to an `ipBlock` that covers the rest of the address space.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    writeln!(out, "apiVersion: networking.k8s.io/v1")?;
+    writeln!(out, "kind: NetworkPolicy")?;
+    writeln!(out, "metadata:")?;
+    writeln!(
+        out,
+        "  name: {}",
+        serde_json::to_string(&context.name(options))?
+    )?;
+    writeln!(out, "  annotations:")?;
+    writeln!(
+        out,
+        "    netrange/description: {}",
+        serde_json::to_string(&context.description(options))?
+    )?;
+    writeln!(out, "spec:")?;
+    writeln!(out, "  podSelector: {{}}")?;
+    writeln!(out, "  policyTypes:")?;
+    writeln!(out, "  - Egress")?;
+    match options.action {
+        Action::Allow if ranges.is_empty() => {
+            // An egress rule without any peers would allow everything
+            writeln!(out, "  egress: []")?;
+        }
+        Action::Allow => {
+            writeln!(out, "  egress:")?;
+            writeln!(out, "  - to:")?;
+            for OutputRange { range, .. } in ranges {
+                writeln!(out, "    - ipBlock:")?;
+                writeln!(out, "        cidr: \"{}\"", range)?;
+            }
+        }
+        Action::Deny => {
+            writeln!(out, "  egress:")?;
+            writeln!(out, "  - to:")?;
+            for (all, is_ipv6) in &[("0.0.0.0/0", false), ("::/0", true)] {
+                writeln!(out, "    - ipBlock:")?;
+                writeln!(out, "        cidr: \"{}\"", all)?;
+                let excepted: Vec<_> = ranges
+                    .iter()
+                    .map(|r| &r.range)
+                    .filter(|r| r.is_ipv6() == *is_ipv6)
+                    .collect();
+                if !excepted.is_empty() {
+                    writeln!(out, "        except:")?;
+                    for range in excepted {
+                        writeln!(out, "        - \"{}\"", range)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::kubernetes::write_ranges;
+    use crate::formats::test_util::render;
+
+    const HEADER: &str = r#"apiVersion: networking.k8s.io/v1
+kind: NetworkPolicy
+metadata:
+  name: "cloud"
+  annotations:
+    netrange/description: "IP ranges"
+spec:
+  podSelector: {}
+  policyTypes:
+  - Egress
+"#;
+
+    #[test]
+    fn test_write_ranges() {
+        let ranges = &["10.0.0.0/24", "2001:db8::/32"];
+        assert_eq!(
+            render(write_ranges, ranges, &["--name", "cloud"]),
+            HEADER.to_string()
+                + r#"  egress:
+  - to:
+    - ipBlock:
+        cidr: "10.0.0.0/24"
+    - ipBlock:
+        cidr: "2001:db8::/32"
+"#
+        );
+        assert_eq!(
+            render(
+                write_ranges,
+                ranges,
+                &["--name", "cloud", "--action", "deny"]
+            ),
+            HEADER.to_string()
+                + r#"  egress:
+  - to:
+    - ipBlock:
+        cidr: "0.0.0.0/0"
+        except:
+        - "10.0.0.0/24"
+    - ipBlock:
+        cidr: "::/0"
+        except:
+        - "2001:db8::/32"
+"#
+        );
+        assert_eq!(
+            render(write_ranges, &[], &["--name", "cloud"]),
+            HEADER.to_string() + "  egress: []\n"
+        );
+    }
+}
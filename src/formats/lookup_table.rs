@@ -0,0 +1,18 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use libnetrangemerge::{write_lookup_table, IpRange};
+use std::io::Write;
+
+/// Write the ranges as a binary lookup table that can be
+/// queried with `libnetrangemerge::LookupTable`.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    _options: &OutputOptions,
+    _context: &OutputContext,
+) -> Result<(), Error> {
+    let ranges: Vec<IpRange> = ranges.iter().map(|r| r.range).collect();
+    write_lookup_table(&ranges, out)?;
+    Ok(())
+}
@@ -0,0 +1,403 @@
+//! A writer for the [MaxMind DB](https://maxmind.github.io/MaxMind-DB/) file format.
+//!
+//! The database is always an ipv6 database. ipv4 ranges are stored in
+//! the `::/96` subtree which is where MaxMind DB readers look up ipv4
+//! addresses in an ipv6 database.
+
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::{bail, Error};
+use libnetrangemerge::{IpRange, Range as _};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const METADATA_START_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+const DATA_SECTION_SEPARATOR_SIZE: u64 = 16;
+
+const TYPE_STRING: u8 = 2;
+const TYPE_DOUBLE: u8 = 3;
+const TYPE_UINT16: u8 = 5;
+const TYPE_UINT32: u8 = 6;
+const TYPE_MAP: u8 = 7;
+const TYPE_INT32: u8 = 8;
+const TYPE_UINT64: u8 = 9;
+const TYPE_ARRAY: u8 = 11;
+const TYPE_BOOLEAN: u8 = 14;
+
+enum Node {
+    Empty,
+    Data(usize),
+    Branch(Box<[Node; 2]>),
+}
+
+impl Node {
+    /// Turn an empty or data node into a branch node with two
+    /// children that are each the same as the original node.
+    fn split(&mut self) {
+        let children = match self {
+            Node::Empty => [Node::Empty, Node::Empty],
+            Node::Data(data) => [Node::Data(*data), Node::Data(*data)],
+            Node::Branch(_) => return,
+        };
+        *self = Node::Branch(Box::new(children));
+    }
+
+    /// Store `data` for every address that starts with the first `depth` bits
+    /// of `bits`. More specific prefixes take precedence over less specific
+    /// ones as long as they are inserted from least to most specific.
+    fn insert(&mut self, bits: u128, depth: u8, data: usize) {
+        if depth == 0 {
+            *self = Node::Data(data);
+            return;
+        }
+        self.split();
+        if let Node::Branch(children) = self {
+            let bit = (bits >> 127) as usize;
+            children[bit].insert(bits << 1, depth - 1, data);
+        }
+    }
+}
+
+/// Return the bits of the address in the ipv6 search tree along with
+/// the number of bits that are significant.
+fn tree_bits(range: &IpRange) -> (u128, u8) {
+    match range.host_address() {
+        IpAddr::V4(addr) => (u32::from(*addr) as u128, range.prefix_length() + 96),
+        IpAddr::V6(addr) => (u128::from(*addr), range.prefix_length()),
+    }
+}
+
+fn write_control(buf: &mut Vec<u8>, type_num: u8, size: usize) -> Result<(), Error> {
+    let (size_bits, size_bytes): (u8, Vec<u8>) = if size < 29 {
+        (size as u8, vec![])
+    } else if size < 285 {
+        (29, vec![(size - 29) as u8])
+    } else if size < 65_821 {
+        (30, ((size - 285) as u16).to_be_bytes().to_vec())
+    } else if size < 16_843_037 {
+        (31, ((size - 65_821) as u32).to_be_bytes()[1..].to_vec())
+    } else {
+        bail!("Value is too large to store in a MaxMind DB: {}", size);
+    };
+    if type_num <= 7 {
+        buf.push((type_num << 5) | size_bits);
+    } else {
+        buf.push(size_bits);
+        buf.push(type_num - 7);
+    }
+    buf.extend(size_bytes);
+    Ok(())
+}
+
+fn write_unsigned(buf: &mut Vec<u8>, type_num: u8, value: u64) -> Result<(), Error> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count();
+    write_control(buf, type_num, bytes.len() - skip)?;
+    buf.extend(&bytes[skip..]);
+    Ok(())
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) -> Result<(), Error> {
+    write_control(buf, TYPE_STRING, value.len())?;
+    buf.extend(value.as_bytes());
+    Ok(())
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) -> Result<(), Error> {
+    match value {
+        Value::Null => bail!("Null values can't be stored in a MaxMind DB"),
+        Value::Bool(val) => write_control(buf, TYPE_BOOLEAN, *val as usize)?,
+        Value::Number(val) => {
+            if let Some(val) = val.as_u64() {
+                let type_num = if val <= u32::MAX as u64 {
+                    TYPE_UINT32
+                } else {
+                    TYPE_UINT64
+                };
+                write_unsigned(buf, type_num, val)?;
+            } else if let Some(val) = val.as_i64() {
+                if val < i32::MIN as i64 {
+                    bail!(
+                        "The value {} is too small to be stored in a MaxMind DB",
+                        val
+                    );
+                }
+                write_control(buf, TYPE_INT32, 4)?;
+                buf.extend(&(val as i32).to_be_bytes());
+            } else if let Some(val) = val.as_f64() {
+                write_control(buf, TYPE_DOUBLE, 8)?;
+                buf.extend(&val.to_be_bytes());
+            }
+        }
+        Value::String(val) => write_string(buf, val)?,
+        Value::Array(vals) => {
+            write_control(buf, TYPE_ARRAY, vals.len())?;
+            for val in vals {
+                write_value(buf, val)?;
+            }
+        }
+        Value::Object(vals) => {
+            write_control(buf, TYPE_MAP, vals.len())?;
+            for (key, val) in vals {
+                write_string(buf, key)?;
+                write_value(buf, val)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Build the data record for a range. If the ranges were read, metadata
+/// values are stored directly unless multiple records with the same range
+/// had different values, in which case all of the values are stored in
+/// an array. Merged ranges always store arrays of values.
+fn range_data(range: &OutputRange, context: &OutputContext) -> Value {
+    let mut data = Map::new();
+    for (key, values) in &range.metadata {
+        let value = if values.len() == 1 && !context.merged {
+            serde_json::to_value(values.iter().next()).unwrap_or(Value::Null)
+        } else {
+            serde_json::to_value(values).unwrap_or(Value::Null)
+        };
+        data.insert(key.to_string(), value);
+    }
+    Value::Object(data)
+}
+
+/// Assign each branch node an index in pre-order, which places the root
+/// node at index 0.
+fn number_nodes<'a>(node: &'a Node, nodes: &mut Vec<&'a [Node; 2]>) {
+    if let Node::Branch(children) = node {
+        nodes.push(children);
+        number_nodes(&children[0], nodes);
+        number_nodes(&children[1], nodes);
+    }
+}
+
+fn write_record(buf: &mut Vec<u8>, left: u32, right: u32, record_size: u16) {
+    match record_size {
+        24 => {
+            buf.extend(&left.to_be_bytes()[1..]);
+            buf.extend(&right.to_be_bytes()[1..]);
+        }
+        28 => {
+            buf.extend(&left.to_be_bytes()[1..]);
+            buf.push((((left >> 24) & 0x0F) << 4) as u8 | ((right >> 24) & 0x0F) as u8);
+            buf.extend(&right.to_be_bytes()[1..]);
+        }
+        _ => {
+            buf.extend(&left.to_be_bytes());
+            buf.extend(&right.to_be_bytes());
+        }
+    }
+}
+
+/// Write the ranges as a MaxMind DB file tagging each range with
+/// the metadata of the records it came from.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    // Combine the metadata of records that have the same range
+    // so that the data for one isn't lost.
+    let mut unique_ranges: BTreeMap<(u8, u128), OutputRange> = BTreeMap::new();
+    for range in ranges {
+        let (bits, depth) = tree_bits(&range.range);
+        let entry = unique_ranges
+            .entry((depth, bits))
+            .or_insert_with(|| OutputRange::new(range.range, range.selected));
+        for (key, values) in &range.metadata {
+            entry
+                .metadata
+                .entry(key)
+                .or_default()
+                .extend(values.iter().cloned());
+        }
+    }
+
+    // Build the data section, sharing identical records
+    let mut data_section = Vec::new();
+    let mut data_offsets: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut root = Node::Empty;
+    // Ranges are ordered by depth, so less specific ranges are inserted first
+    for ((depth, bits), range) in &unique_ranges {
+        let mut data = Vec::new();
+        write_value(&mut data, &range_data(range, context))?;
+        let offset = match data_offsets.get(&data) {
+            Some(offset) => *offset,
+            None => {
+                let offset = data_section.len();
+                data_section.extend(&data);
+                data_offsets.insert(data, offset);
+                offset
+            }
+        };
+        root.insert(*bits, *depth, offset);
+    }
+    // The root of the search tree must always be a branch node
+    root.split();
+
+    let mut nodes = Vec::new();
+    number_nodes(&root, &mut nodes);
+    let node_index: HashMap<*const [Node; 2], u32> = nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, n)| (*n as *const [Node; 2], idx as u32))
+        .collect();
+    let node_count = nodes.len() as u64;
+    let max_record = node_count + DATA_SECTION_SEPARATOR_SIZE + data_section.len() as u64;
+    let record_size: u16 = if max_record < 1 << 24 {
+        24
+    } else if max_record < 1 << 28 {
+        28
+    } else if max_record < 1 << 32 {
+        32
+    } else {
+        bail!("Too much data to store in a MaxMind DB");
+    };
+
+    let record_value = |node: &Node| -> u32 {
+        match node {
+            Node::Empty => node_count as u32,
+            Node::Data(offset) => {
+                (node_count + DATA_SECTION_SEPARATOR_SIZE) as u32 + *offset as u32
+            }
+            Node::Branch(children) => node_index[&(&**children as *const [Node; 2])],
+        }
+    };
+    let mut tree = Vec::with_capacity(nodes.len() * record_size as usize / 4);
+    for children in &nodes {
+        let left = record_value(&children[0]);
+        let right = record_value(&children[1]);
+        write_record(&mut tree, left, right, record_size);
+    }
+
+    let build_epoch = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut metadata = Vec::new();
+    write_control(&mut metadata, TYPE_MAP, 9)?;
+    write_string(&mut metadata, "binary_format_major_version")?;
+    write_unsigned(&mut metadata, TYPE_UINT16, 2)?;
+    write_string(&mut metadata, "binary_format_minor_version")?;
+    write_unsigned(&mut metadata, TYPE_UINT16, 0)?;
+    write_string(&mut metadata, "build_epoch")?;
+    write_unsigned(&mut metadata, TYPE_UINT64, build_epoch)?;
+    write_string(&mut metadata, "database_type")?;
+    write_string(&mut metadata, &options.name)?;
+    write_string(&mut metadata, "description")?;
+    let mut description = Map::new();
+    description.insert(
+        "en".to_string(),
+        Value::String(context.description(options)),
+    );
+    write_value(&mut metadata, &Value::Object(description))?;
+    write_string(&mut metadata, "ip_version")?;
+    write_unsigned(&mut metadata, TYPE_UINT16, 6)?;
+    write_string(&mut metadata, "languages")?;
+    write_value(
+        &mut metadata,
+        &Value::Array(vec![Value::String("en".to_string())]),
+    )?;
+    write_string(&mut metadata, "node_count")?;
+    write_unsigned(&mut metadata, TYPE_UINT32, node_count)?;
+    write_string(&mut metadata, "record_size")?;
+    write_unsigned(&mut metadata, TYPE_UINT16, record_size as u64)?;
+
+    out.write_all(&tree)?;
+    out.write_all(&[0; DATA_SECTION_SEPARATOR_SIZE as usize])?;
+    out.write_all(&data_section)?;
+    out.write_all(METADATA_START_MARKER)?;
+    out.write_all(&metadata)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::mmdb::{write_ranges, write_value};
+    use crate::formats::{OutputContext, OutputRange};
+    use crate::OutputOptions;
+    use maxminddb::{MaxMindDBError, Reader};
+    use serde_json::{json, Value};
+    use structopt::StructOpt;
+
+    fn output_range(range: &str, region: &str, service: &str) -> OutputRange {
+        let mut output_range = OutputRange::new(range.parse().unwrap(), true);
+        output_range
+            .metadata
+            .entry("provider")
+            .or_default()
+            .insert("aws".to_string().into());
+        output_range
+            .metadata
+            .entry("region")
+            .or_default()
+            .insert(region.to_string().into());
+        output_range
+            .metadata
+            .entry("service")
+            .or_default()
+            .insert(service.to_string().into());
+        output_range
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ranges = vec![
+            output_range("52.94.0.0/22", "us-east-1", "AMAZON"),
+            output_range("52.94.2.0/24", "us-east-1", "EC2"),
+            output_range("52.95.0.0/24", "us-west-2", "S3"),
+            output_range("52.95.0.0/24", "us-west-2", "AMAZON"),
+            output_range("2600:1f00::/24", "us-east-1", "EC2"),
+        ];
+        let options = OutputOptions::from_iter(&["netrange", "--name", "netrange-test"]);
+        let context = OutputContext {
+            service: Some("aws".to_string()),
+            ..Default::default()
+        };
+        let mut db = Vec::new();
+        write_ranges(&mut db, &ranges, &options, &context).unwrap();
+
+        let reader = Reader::from_source(db).unwrap();
+        assert_eq!(reader.metadata.database_type, "netrange-test");
+        assert_eq!(reader.metadata.ip_version, 6);
+
+        let lookup = |ip: &str| reader.lookup::<Value>(ip.parse().unwrap());
+        assert_eq!(
+            lookup("52.94.1.1").unwrap(),
+            json!({"provider": "aws", "region": "us-east-1", "service": "AMAZON"}),
+        );
+        assert_eq!(
+            lookup("52.94.2.200").unwrap(),
+            json!({"provider": "aws", "region": "us-east-1", "service": "EC2"}),
+        );
+        assert_eq!(
+            lookup("52.95.0.1").unwrap(),
+            json!({"provider": "aws", "region": "us-west-2", "service": ["AMAZON", "S3"]}),
+        );
+        assert_eq!(
+            lookup("2600:1f00::1").unwrap(),
+            json!({"provider": "aws", "region": "us-east-1", "service": "EC2"}),
+        );
+        match lookup("52.96.0.1") {
+            Err(MaxMindDBError::AddressNotFoundError(_)) => {}
+            _ => panic!("Expected AddressNotFoundError failure"),
+        }
+        match lookup("2700::1") {
+            Err(MaxMindDBError::AddressNotFoundError(_)) => {}
+            _ => panic!("Expected AddressNotFoundError failure"),
+        }
+    }
+
+    #[test]
+    fn test_write_value_numbers() {
+        let mut buf = Vec::new();
+        write_value(&mut buf, &json!(i32::MIN)).unwrap();
+        write_value(&mut buf, &json!(u64::MAX)).unwrap();
+        write_value(&mut buf, &json!(1.5)).unwrap();
+        assert!(write_value(&mut buf, &json!(i32::MIN as i64 - 1)).is_err());
+    }
+}
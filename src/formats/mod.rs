@@ -0,0 +1,216 @@
+use crate::utils::filter_select::MetadataValue;
+use crate::OutputOptions;
+use anyhow::{bail, Error};
+use libnetrangemerge::{IpRange, Range as _};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+pub mod apache;
+pub mod bird;
+pub mod cisco;
+pub mod cloudformation;
+pub mod csv;
+pub mod envoy;
+pub mod haproxy;
+pub mod json;
+pub mod juniper;
+pub mod kubernetes;
+pub mod lookup_table;
+pub mod mmdb;
+pub mod nginx;
+pub mod plain;
+pub mod template;
+pub mod terraform;
+
+/// Whether generated access rules should permit or reject
+/// the ranges that are written out.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Action::Allow),
+            "deny" => Ok(Action::Deny),
+            _ => bail!("Invalid action: {}", s),
+        }
+    }
+}
+
+/// A range to be written out along with the metadata of
+/// the records that it was built from.
+#[derive(Clone, Debug)]
+pub struct OutputRange {
+    pub range: IpRange,
+    pub selected: bool,
+    pub metadata: BTreeMap<&'static str, BTreeSet<MetadataValue>>,
+}
+
+impl OutputRange {
+    pub fn new(range: IpRange, selected: bool) -> OutputRange {
+        OutputRange {
+            range,
+            selected,
+            metadata: BTreeMap::new(),
+        }
+    }
+}
+
+/// Information about where the ranges being written out came from.
+#[derive(Clone, Debug, Default)]
+pub struct OutputContext {
+    pub service: Option<String>,
+    pub filter: Option<String>,
+    /// `true` if the ranges were merged, in which case each range may
+    /// have been built from multiple records with different metadata.
+    pub merged: bool,
+    /// The number of the chunk being written, starting at 1, when the
+    /// output is split into chunks.
+    pub chunk: Option<usize>,
+    /// The metadata of the service's range file that the ranges
+    /// came from, such as AWS's "syncToken".
+    pub source_metadata: BTreeMap<&'static str, MetadataValue>,
+}
+
+impl OutputContext {
+    /// Return the description to attach to generated resources. If the user
+    /// didn't provide one, a description is built from the source service
+    /// and the filter program.
+    pub fn description(&self, options: &OutputOptions) -> String {
+        if let Some(description) = &options.description {
+            return description.clone();
+        }
+        let mut description = match &self.service {
+            Some(service) => format!("IP ranges for {}", service),
+            None => "IP ranges".to_string(),
+        };
+        if let Some(filter) = &self.filter {
+            description.push_str(&format!(" matching filter: {}", filter.trim()));
+        }
+        description
+    }
+
+    /// Return the name to give generated variables, lists, and resources.
+    /// When the output is split into chunks, the chunk number is added so
+    /// that the chunks can be loaded side by side.
+    pub fn name(&self, options: &OutputOptions) -> String {
+        self.name_with_separator(options, "-")
+    }
+
+    /// Like `name`, but with `separator` between the name and the chunk
+    /// number for formats whose names can't contain a "-".
+    pub fn name_with_separator(&self, options: &OutputOptions, separator: &str) -> String {
+        match self.chunk {
+            Some(chunk) => format!("{}{}{}", options.name, separator, chunk),
+            None => options.name.clone(),
+        }
+    }
+}
+
+/// Return the `ge` and `le` bounds that apply to `range`. Bounds that
+/// wouldn't be longer than the prefix length of the range are omitted as
+/// they would either be redundant or invalid for that range.
+pub fn prefix_length_bounds(
+    range: &IpRange,
+    options: &OutputOptions,
+) -> Result<(Option<u8>, Option<u8>), Error> {
+    let (ge, le, max_length) = if range.is_ipv6() {
+        (options.ipv6_ge, options.ipv6_le, 128)
+    } else {
+        (options.ipv4_ge, options.ipv4_le, 32)
+    };
+    for bound in ge.iter().chain(le.iter()) {
+        if *bound > max_length {
+            bail!(
+                "Invalid prefix length bound: {}. Bound must be {} or less",
+                bound,
+                max_length
+            );
+        }
+    }
+    if let (Some(ge), Some(le)) = (ge, le) {
+        if ge > le {
+            bail!(
+                "The ge bound ({}) must not be larger than the le bound ({})",
+                ge,
+                le
+            );
+        }
+    }
+    let ge = ge.filter(|ge| *ge > range.prefix_length());
+    let le = le.filter(|le| *le > range.prefix_length());
+    Ok((ge, le))
+}
+
+/// Helpers for testing the output of the formats.
+#[cfg(test)]
+pub mod test_util {
+    use crate::formats::{OutputContext, OutputRange};
+    use crate::utils::format_config::WriteRangesFunc;
+    use crate::OutputOptions;
+    use std::iter;
+    use structopt::StructOpt;
+
+    /// Parse output options from command line arguments.
+    pub fn options(args: &[&str]) -> OutputOptions {
+        OutputOptions::from_iter(iter::once("netrange").chain(args.iter().copied()))
+    }
+
+    pub fn output_ranges(ranges: &[&str]) -> Vec<OutputRange> {
+        ranges
+            .iter()
+            .map(|r| OutputRange::new(r.parse().unwrap(), true))
+            .collect()
+    }
+
+    /// Write `ranges` with `write_ranges_func` and the options parsed from
+    /// `args`, returning the output as a string.
+    pub fn render_with_context(
+        write_ranges_func: WriteRangesFunc,
+        ranges: &[OutputRange],
+        args: &[&str],
+        context: &OutputContext,
+    ) -> String {
+        let mut out = Vec::new();
+        write_ranges_func(&mut out, ranges, &options(args), context).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Ranges built from records of two regions, with the second range
+    /// merged from records of both regions.
+    pub fn merged_ranges() -> Vec<OutputRange> {
+        let mut ranges = output_ranges(&["10.0.0.0/24", "2001:db8::/32"]);
+        ranges[0].metadata.insert(
+            "region",
+            vec!["us-east-1".to_string().into()].into_iter().collect(),
+        );
+        ranges[1].selected = false;
+        ranges[1].metadata.insert(
+            "region",
+            vec![
+                "eu-west-1".to_string().into(),
+                "us-east-1".to_string().into(),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        ranges[1]
+            .metadata
+            .insert("ipv6", vec![true.into()].into_iter().collect());
+        ranges
+    }
+
+    pub fn render(write_ranges_func: WriteRangesFunc, ranges: &[&str], args: &[&str]) -> String {
+        render_with_context(
+            write_ranges_func,
+            &output_ranges(ranges),
+            args,
+            &OutputContext::default(),
+        )
+    }
+}
@@ -0,0 +1,77 @@
+use crate::formats::{Action, OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use std::io::Write;
+
+/// Write the ranges as a list of `allow` or `deny` directives followed
+/// by a catch-all directive with the opposite polarity.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    _context: &OutputContext,
+) -> Result<(), Error> {
+    let (directive, fallback) = match options.action {
+        Action::Allow => ("allow", "deny"),
+        Action::Deny => ("deny", "allow"),
+    };
+    for OutputRange { range, .. } in ranges {
+        writeln!(out, "{} {};", directive, range)?;
+    }
+    writeln!(out, "{} all;", fallback)?;
+    Ok(())
+}
+
+/// Write the ranges as a `geo` block that sets a variable to `1` for
+/// allowed addresses and `0` for denied addresses.
+pub fn write_geo_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let (value, default) = match options.action {
+        Action::Allow => (1, 0),
+        Action::Deny => (0, 1),
+    };
+    // Variable names can't contain a "-"
+    writeln!(out, "geo ${} {{", context.name_with_separator(options, "_"))?;
+    writeln!(out, "    default {};", default)?;
+    for OutputRange { range, .. } in ranges {
+        writeln!(out, "    {} {};", range, value)?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::nginx::{write_geo_ranges, write_ranges};
+    use crate::formats::test_util::render;
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_ranges() {
+        assert_eq!(
+            render(write_ranges, RANGES, &[]),
+            "allow 10.0.0.0/24;\nallow 2001:db8::/32;\ndeny all;\n"
+        );
+        assert_eq!(
+            render(write_ranges, RANGES, &["--action", "deny"]),
+            "deny 10.0.0.0/24;\ndeny 2001:db8::/32;\nallow all;\n"
+        );
+    }
+
+    #[test]
+    fn test_write_geo_ranges() {
+        assert_eq!(
+            render(write_geo_ranges, RANGES, &["--name", "cloud"]),
+            "geo $cloud {\n    default 0;\n    10.0.0.0/24 1;\n    2001:db8::/32 1;\n}\n"
+        );
+        assert_eq!(
+            render(write_geo_ranges, RANGES, &["--action", "deny"]),
+            "geo $netrange {\n    default 1;\n    10.0.0.0/24 0;\n    2001:db8::/32 0;\n}\n"
+        );
+    }
+}
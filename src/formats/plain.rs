@@ -0,0 +1,16 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use std::io::Write;
+
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    _options: &OutputOptions,
+    _context: &OutputContext,
+) -> Result<(), Error> {
+    for OutputRange { range, .. } in ranges {
+        writeln!(out, "{}", range)?;
+    }
+    Ok(())
+}
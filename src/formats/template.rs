@@ -0,0 +1,122 @@
+use crate::formats::json::metadata_value;
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::{anyhow, Error};
+use cidr::Cidr as _;
+use libnetrangemerge::Range as _;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{Read, Write};
+use tinytemplate::TinyTemplate;
+
+fn range_value(range: &OutputRange, context: &OutputContext) -> Result<Value, Error> {
+    let r = &range.range;
+    let cidr = cidr::IpCidr::new(*r.host_address(), r.prefix_length())?;
+    Ok(json!({
+        "range": r.to_string(),
+        "prefix_length": r.prefix_length(),
+        "is_ipv4": !r.is_ipv6(),
+        "is_ipv6": r.is_ipv6(),
+        "first_address": cidr.first_address().to_string(),
+        "last_address": cidr.last_address().to_string(),
+        "selected": range.selected,
+        "metadata": metadata_value(range, context)?,
+    }))
+}
+
+/// Render the ranges using the template file given by the
+/// `--output-template` option.
+///
+/// The template is rendered with [TinyTemplate](https://docs.rs/tinytemplate)
+/// and has access to the list of ranges as `ranges` and also
+/// split up by address family as `ipv4_ranges` and `ipv6_ranges`.
+/// Text outside of the loops over those lists acts as a header or footer.
+pub fn write_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let template_file = options
+        .output_template
+        .as_ref()
+        .ok_or_else(|| anyhow!("No output template was provided"))?;
+    let mut template = String::new();
+    File::open(template_file)?.read_to_string(&mut template)?;
+
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&tinytemplate::format_unescaped);
+    tt.add_template("output", &template)?;
+
+    let values = ranges
+        .iter()
+        .map(|r| range_value(r, context))
+        .collect::<Result<Vec<Value>, Error>>()?;
+    let (ipv6_values, ipv4_values): (Vec<_>, Vec<_>) = values
+        .iter()
+        .cloned()
+        .partition(|v| v["is_ipv6"] == Value::Bool(true));
+    let template_context = json!({
+        "service": context.service,
+        "filter": context.filter,
+        "name": options.name,
+        "description": context.description(options),
+        "chunk": context.chunk,
+        "source": context.source_metadata,
+        "ranges": values,
+        "ipv4_ranges": ipv4_values,
+        "ipv6_ranges": ipv6_values,
+    });
+
+    write!(out, "{}", tt.render("output", &template_context)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::template::write_ranges;
+    use crate::formats::test_util::{merged_ranges, options, render_with_context};
+    use crate::formats::OutputContext;
+    use std::fs;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_write_ranges() {
+        let template_file = NamedTempFile::new().unwrap();
+        fs::write(
+            template_file.path(),
+            "# {name}: {description}\n\
+             {{ for r in ipv4_ranges }}v4 {r.range} {r.first_address}-{r.last_address} {r.metadata.region}\n{{ endfor }}\
+             {{ for r in ipv6_ranges }}v6 {r.range} /{r.prefix_length} {r.selected}\n{{ endfor }}",
+        )
+        .unwrap();
+        let context = OutputContext {
+            service: Some("aws".to_string()),
+            ..Default::default()
+        };
+        let output = render_with_context(
+            write_ranges,
+            &merged_ranges(),
+            &["--output-template", template_file.path().to_str().unwrap()],
+            &context,
+        );
+        assert_eq!(
+            output,
+            "# netrange: IP ranges for aws\n\
+             v4 10.0.0.0/24 10.0.0.0-10.0.0.255 us-east-1\n\
+             v6 2001:db8::/32 /32 false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_ranges_without_template() {
+        let mut out = Vec::new();
+        assert!(write_ranges(
+            &mut out,
+            &merged_ranges(),
+            &options(&[]),
+            &OutputContext::default()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::OutputOptions;
+use anyhow::Error;
+use serde_json::json;
+use std::io::Write;
+
+fn range_strings(ranges: &[OutputRange]) -> Vec<String> {
+    ranges.iter().map(|r| r.range.to_string()).collect()
+}
+
+/// Write the ranges as a `.tf.json` file that declares a `list(string)`
+/// variable with the ranges as its default value.
+pub fn write_variable_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let doc = json!({
+        "variable": {
+            context.name(options): {
+                "description": context.description(options),
+                "type": "list(string)",
+                "default": range_strings(ranges),
+            }
+        }
+    });
+    serde_json::to_writer_pretty(&mut *out, &doc)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Write the ranges as a `.tf.json` file that declares a local value
+/// containing the ranges.
+pub fn write_locals_ranges(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let doc = json!({
+        "//": context.description(options),
+        "locals": {
+            context.name(options): range_strings(ranges),
+        }
+    });
+    serde_json::to_writer_pretty(&mut *out, &doc)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::terraform::{write_locals_ranges, write_variable_ranges};
+    use crate::formats::test_util::render;
+    use serde_json::{json, Value};
+
+    const RANGES: &[&str] = &["10.0.0.0/24", "2001:db8::/32"];
+
+    #[test]
+    fn test_write_variable_ranges() {
+        let output = render(write_variable_ranges, RANGES, &["--name", "cloud"]);
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!({
+                "variable": {
+                    "cloud": {
+                        "description": "IP ranges",
+                        "type": "list(string)",
+                        "default": ["10.0.0.0/24", "2001:db8::/32"],
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_locals_ranges() {
+        let output = render(write_locals_ranges, RANGES, &["--description", "Cloud"]);
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!({
+                "//": "Cloud",
+                "locals": {"netrange": ["10.0.0.0/24", "2001:db8::/32"]},
+            })
+        );
+    }
+}
@@ -1,5 +1,7 @@
-use crate::utils::filter_select::RangesWithMetadata;
-use anyhow::Error;
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
+use crate::utils::timestamp::parse_timestamp;
+use anyhow::{bail, Error};
 use libnetrangemerge::IpRange;
 use serde::Deserialize;
 use std::collections::HashMap;
@@ -13,6 +15,10 @@ The AWS service has the following filterable values:
   * region (string) - The region, for example: "us-east-1", "us-gov-west-1", "GLOBAL", etc
   * service (string) - The service, for example: "EC2", "AMAZON", "S3", "API_GATEWAY", etc
   * network_border_group (string) - The network border group
+  * syncToken (string) - The publication time of the file, in seconds since
+    the Unix epoch. The same for every range.
+  * createDate (string) - The publication time of the file, for example:
+    "2021-03-03-12-00-00". The same for every range.
 
 See https://docs.aws.amazon.com/general/latest/gr/aws-ip-ranges.html for more information
 "###;
@@ -20,9 +26,7 @@ See https://docs.aws.amazon.com/general/latest/gr/aws-ip-ranges.html for more in
 #[derive(Deserialize, Debug)]
 #[allow(non_snake_case)]
 struct AwsRanges {
-    #[allow(dead_code)]
     syncToken: String,
-    #[allow(dead_code)]
     createDate: String,
     prefixes: Vec<AwsIpv4Range>,
     ipv6_prefixes: Vec<AwsIpv6Range>,
@@ -44,17 +48,34 @@ struct AwsIpv6Range {
     network_border_group: String,
 }
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    Ok(
-        reqwest::blocking::get("https://ip-ranges.amazonaws.com/ip-ranges.json")?
-            .error_for_status()?,
-    )
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetcher.fetch("https://ip-ranges.amazonaws.com/ip-ranges.json")
 }
 
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
+/// Parse the "createDate" of the file, which is in the
+/// format "YYYY-MM-DD-hh-mm-ss" in UTC.
+fn parse_create_date(create_date: &str) -> Result<u64, Error> {
+    let parts: Vec<&str> = create_date.split('-').collect();
+    if parts.len() != 6 {
+        bail!("Invalid createDate: {}", create_date);
+    }
+    parse_timestamp(&format!(
+        "{}-{}-{}T{}:{}:{}Z",
+        parts[0], parts[1], parts[2], parts[3], parts[4], parts[5]
+    ))
+}
+
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
     let mut data = String::new();
     reader.read_to_string(&mut data)?;
     let ranges: AwsRanges = serde_json::from_str(&data)?;
+
+    // A createDate that can't be parsed only matters to --max-source-age
+    let created_at = parse_create_date(&ranges.createDate).ok();
+    let mut file_metadata = HashMap::new();
+    file_metadata.insert("syncToken", ranges.syncToken.into());
+    file_metadata.insert("createDate", ranges.createDate.into());
+
     let ipv4_ranges = ranges.prefixes.into_iter().map(|range| {
         let mut metadata = HashMap::new();
         metadata.insert("region", range.region.into());
@@ -75,5 +96,11 @@ pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>,
 
         Ok(RangesWithMetadata::new(metadata, ranges))
     });
-    ipv4_ranges.chain(ipv6_ranges).collect()
+    Ok(CloudRanges {
+        file_metadata,
+        created_at,
+        ranges: ipv4_ranges
+            .chain(ipv6_ranges)
+            .collect::<Result<_, Error>>()?,
+    })
 }
@@ -1,5 +1,6 @@
-use crate::utils::filter_select::RangesWithMetadata;
-use anyhow::{bail, Error};
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
+use anyhow::{anyhow, Error};
 use libnetrangemerge::IpRange;
 use serde::Deserialize;
 use std::collections::{BTreeMap, HashMap};
@@ -17,16 +18,45 @@ The Azure service has the following filterable values:
   * platform (string) - The platform, always: "Azure"
   * systemService (string) - The name of the system service, for example: "AzureBackup" or "AzureSQL"
   * networkFeatures (table) - The network features, for example: "API" or "NSG"
+  * changeNumber (number) - The version of the file. The same for every range.
+  * cloud (string) - The cloud that the file is for, for example: "Public",
+    "AzureGovernment", "China" or "AzureGermany". The same for every range.
+
+Each Azure cloud publishes its own file. They are the "azure" (Public),
+"azure-government", "azure-china" and "azure-germany" services. Files saved
+from the Service Tag Discovery API, for example with
+`az network list-service-tags`, can also be read.
 
 Its a bit unclear where the best documentation is for this format, but this
 API call seems to largely correspond to the JSON file:
 https://docs.microsoft.com/en-us/rest/api/virtualnetwork/servicetags/list
 "###;
 
+/// A change number, which is a number in the downloadable files
+/// and a string in the Service Tag Discovery API's responses.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ChangeNumber {
+    Number(i64),
+    String(String),
+}
+
+impl ChangeNumber {
+    fn value(self) -> Result<i64, Error> {
+        match self {
+            ChangeNumber::Number(n) => Ok(n),
+            ChangeNumber::String(s) => s
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid Azure change number: {}", s)),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
-#[allow(non_snake_case, dead_code)]
+#[allow(non_snake_case)]
 struct AzureRanges {
-    changeNumber: i64,
+    changeNumber: ChangeNumber,
     cloud: String,
     values: Vec<AzureRange>,
 }
@@ -38,41 +68,167 @@ struct AzureRange {
     properties: AzureRangeProperties,
 }
 
+/// The properties of a service tag. Fields that aren't in every
+/// response of the Service Tag Discovery API have defaults.
 #[derive(Deserialize, Debug)]
-#[allow(non_snake_case, dead_code)]
+#[allow(non_snake_case)]
 struct AzureRangeProperties {
-    changeNumber: i64,
+    #[serde(default)]
     region: String,
+    #[serde(default)]
     regionId: i64,
+    #[serde(default = "default_platform")]
     platform: String,
+    #[serde(default)]
     systemService: String,
     addressPrefixes: Vec<String>,
     networkFeatures: Option<Vec<String>>,
 }
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .get("https://www.microsoft.com/en-us/download/confirmation.aspx?id=56519")
-        .send()?
-        .error_for_status()?;
-    let body = response.text()?;
-    let regex = regex::Regex::new(r"https://download.*?\.json")?;
-    if let Some(m) = regex.find(&body) {
-        let url = m.as_str();
-        let response = client.get(url).send()?.error_for_status()?;
-        Ok(response)
-    } else {
-        bail!("Unable to find download URL for Azure IP range file");
+fn default_platform() -> String {
+    "Azure".to_string()
+}
+
+/// An Azure cloud, each of which publishes its own service tags file.
+struct AzureCloud {
+    service: &'static str,
+    /// The ID of the file's page in the Microsoft Download Center.
+    download_id: u32,
+    /// The name of the cloud in the file's name,
+    /// such as "ServiceTags_Public_20210301.json".
+    file_name: &'static str,
+}
+
+const PUBLIC: AzureCloud = AzureCloud {
+    service: "azure",
+    download_id: 56519,
+    file_name: "Public",
+};
+const GOVERNMENT: AzureCloud = AzureCloud {
+    service: "azure-government",
+    download_id: 57063,
+    file_name: "AzureGovernment",
+};
+const CHINA: AzureCloud = AzureCloud {
+    service: "azure-china",
+    download_id: 57062,
+    file_name: "China",
+};
+const GERMANY: AzureCloud = AzureCloud {
+    service: "azure-germany",
+    download_id: 57064,
+    file_name: "AzureGermany",
+};
+
+/// Find the URL of the newest service tags file of `cloud` in a page
+/// of the Download Center. The URL may be in a link or in JSON embedded
+/// in the page, where its slashes may be escaped.
+fn find_download_url(page: &str, cloud: &AzureCloud) -> Option<String> {
+    let page = page
+        .replace("\\/", "/")
+        .replace("\\u002F", "/")
+        .replace("\\u002f", "/");
+    let regex = regex::Regex::new(&format!(
+        r#"https://download\.microsoft\.com/download/[^"'\s<>]*?ServiceTags_{}_(\d+)\.json"#,
+        cloud.file_name
+    ))
+    .unwrap();
+    // The file names end with the date that the file was published,
+    // so the newest file has the largest number
+    regex
+        .captures_iter(&page)
+        .max_by_key(|c| c[1].parse::<u64>().unwrap_or(0))
+        .map(|c| c[0].to_string())
+}
+
+/// The pages of the Download Center that have had the link to the file of
+/// `cloud` over time, in the order that they are tried.
+fn download_pages(cloud: &AzureCloud) -> Vec<String> {
+    vec![
+        format!(
+            "https://www.microsoft.com/en-us/download/details.aspx?id={}",
+            cloud.download_id
+        ),
+        format!(
+            "https://www.microsoft.com/en-us/download/confirmation.aspx?id={}",
+            cloud.download_id
+        ),
+    ]
+}
+
+/// Find the URL of the newest file of `cloud` in the first of `pages` that
+/// links to it, reading each page with `get_page`. If the link isn't found on
+/// any of them, the error says what went wrong with each page.
+fn find_download_url_in_pages(
+    cloud: &AzureCloud,
+    pages: &[String],
+    get_page: impl Fn(&str) -> Result<String, Error>,
+) -> Result<String, Error> {
+    let mut errors = Vec::new();
+    for page in pages {
+        match get_page(page) {
+            Ok(body) => match find_download_url(&body, cloud) {
+                Some(url) => return Ok(url),
+                None => errors.push(format!("{}: no download link found", page)),
+            },
+            Err(err) => errors.push(format!("{}: {}", page, err)),
+        }
     }
+    Err(anyhow!(
+        "Unable to find download URL for Azure {} IP range file. Use \
+         --url {}=<url> to give the URL of the file.\n{}",
+        cloud.file_name,
+        cloud.service,
+        errors.join("\n")
+    ))
+}
+
+/// Download the service tags file of `cloud`.
+///
+/// Microsoft doesn't publish a stable URL for these files. Each week's file
+/// has the date in its name and is only linked to from the file's page in the
+/// Download Center. So unless the URL is given with "--url", the link is
+/// looked for on that page, falling back to the other page that has had it,
+/// which works for as long as the pages keep their current form. Giving the
+/// URL of a mirror with "--url", or reading the file from a mirror directory,
+/// avoids depending on the pages at all.
+fn fetch_cloud_ranges(fetcher: &Fetcher, cloud: &AzureCloud) -> Result<Vec<u8>, Error> {
+    if let Some(url) = fetcher.url_override() {
+        return fetcher.fetch(url);
+    }
+    let url = find_download_url_in_pages(cloud, &download_pages(cloud), |page| {
+        Ok(fetcher.get(page)?.error_for_status()?.text()?)
+    })?;
+    fetcher.fetch(&url)
+}
+
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetch_cloud_ranges(fetcher, &PUBLIC)
+}
+
+pub fn fetch_government_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetch_cloud_ranges(fetcher, &GOVERNMENT)
+}
+
+pub fn fetch_china_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetch_cloud_ranges(fetcher, &CHINA)
+}
+
+pub fn fetch_germany_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetch_cloud_ranges(fetcher, &GERMANY)
 }
 
 #[allow(non_snake_case)]
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
     let mut data = String::new();
     reader.read_to_string(&mut data)?;
     let ranges: AzureRanges = serde_json::from_str(&data)?;
-    ranges
+
+    let mut file_metadata = HashMap::new();
+    file_metadata.insert("changeNumber", ranges.changeNumber.value()?.into());
+    file_metadata.insert("cloud", ranges.cloud.into());
+
+    let ranges = ranges
         .values
         .into_iter()
         .map(|range| {
@@ -119,5 +275,102 @@ pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>,
 
             Ok(RangesWithMetadata::new(metadata, ranges))
         })
-        .collect()
+        .collect::<Result<_, Error>>()?;
+    Ok(CloudRanges {
+        file_metadata,
+        // The file doesn't say when it was created
+        created_at: None,
+        ranges,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        download_pages, find_download_url, find_download_url_in_pages, load_ranges, CHINA, PUBLIC,
+    };
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_find_download_url() {
+        let page = r#"<a href="https://download.microsoft.com/download/7/1/D/71D86715/ServiceTags_Public_20210301.json">
+            {"url":"https:\/\/download.microsoft.com\/download\/7\/1\/D\/71D86715\/ServiceTags_Public_20210308.json"}"#;
+        assert_eq!(
+            find_download_url(page, &PUBLIC).unwrap(),
+            "https://download.microsoft.com/download/7/1/D/71D86715/ServiceTags_Public_20210308.json"
+        );
+        assert_eq!(find_download_url(page, &CHINA), None);
+    }
+
+    #[test]
+    fn test_download_page_fallback() {
+        const URL: &str =
+            "https://download.microsoft.com/download/7/1/D/71D86715/ServiceTags_Public_20210308.json";
+        let pages = download_pages(&PUBLIC);
+        assert!(pages[0].contains("/details.aspx?id=56519"));
+        assert!(pages[1].contains("/confirmation.aspx?id=56519"));
+
+        // The link is on the details page
+        let url = find_download_url_in_pages(&PUBLIC, &pages, |page| {
+            assert_eq!(page, pages[0]);
+            Ok(format!("<a href=\"{}\">", URL))
+        });
+        assert_eq!(url.unwrap(), URL);
+
+        // The details page has no link, or can't be read
+        for details in &[Ok("<html></html>".to_string()), Err("Not Found")] {
+            let url = find_download_url_in_pages(&PUBLIC, &pages, |page| {
+                if page.contains("details") {
+                    details.clone().map_err(|e| anyhow!(e))
+                } else {
+                    Ok(format!("<a href=\"{}\">", URL))
+                }
+            });
+            assert_eq!(url.unwrap(), URL);
+        }
+
+        // Neither page has the link
+        let err = find_download_url_in_pages(&PUBLIC, &pages, |page| {
+            if page.contains("details") {
+                Err(anyhow!("Not Found"))
+            } else {
+                Ok(String::new())
+            }
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("--url azure=<url>"), "{}", err);
+        assert!(err.contains("details.aspx?id=56519: Not Found"), "{}", err);
+        assert!(
+            err.contains("confirmation.aspx?id=56519: no download link found"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_load_discovery_api_ranges() {
+        let data = r#"{
+            "changeNumber": "42",
+            "cloud": "Public",
+            "id": "/subscriptions/1/providers/Microsoft.Network/serviceTags/Public",
+            "name": "Public",
+            "type": "Microsoft.Network/serviceTags",
+            "values": [{
+                "id": "AzureCloud",
+                "name": "AzureCloud",
+                "properties": {
+                    "addressPrefixes": ["10.0.0.0/24", "2603:1000::/40"],
+                    "changeNumber": "7",
+                    "region": "",
+                    "state": "GA",
+                    "systemService": ""
+                },
+                "serviceTagChangeNumber": "42"
+            }]
+        }"#;
+        let ranges = load_ranges(&mut data.as_bytes()).unwrap();
+        assert_eq!(ranges.file_metadata["changeNumber"], 42.into());
+        assert_eq!(ranges.ranges.len(), 1);
+    }
 }
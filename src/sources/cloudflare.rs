@@ -1,4 +1,5 @@
-use crate::utils::filter_select::RangesWithMetadata;
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
 use anyhow::Error;
 use libnetrangemerge::IpRange;
 use serde::Deserialize;
@@ -27,11 +28,11 @@ struct CloudflareRangesResult {
     ipv6_cidrs: Vec<String>,
 }
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    Ok(reqwest::blocking::get("https://api.cloudflare.com/client/v4/ips")?.error_for_status()?)
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetcher.fetch("https://api.cloudflare.com/client/v4/ips")
 }
 
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
     let mut data = String::new();
     reader.read_to_string(&mut data)?;
     let cf_ranges: CloudflareRanges = serde_json::from_str(&data)?;
@@ -42,5 +43,8 @@ pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>,
         .chain(cf_ranges.result.ipv6_cidrs.into_iter())
         .map(|range| Ok(IpRange::from_str(&range)?))
         .collect::<Result<Vec<IpRange>, Error>>()?;
-    Ok(vec![RangesWithMetadata::new(HashMap::new(), ip_ranges)])
+    Ok(CloudRanges::new(vec![RangesWithMetadata::new(
+        HashMap::new(),
+        ip_ranges,
+    )]))
 }
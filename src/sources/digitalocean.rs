@@ -1,4 +1,5 @@
-use crate::utils::filter_select::RangesWithMetadata;
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
 use anyhow::{bail, Error};
 use csv;
 use libnetrangemerge::IpRange;
@@ -26,16 +27,16 @@ but there appears to be little or no documentation about it. The above field nam
 best guess.
 "###;
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    Ok(reqwest::blocking::get("https://digitalocean.com/geo/google.csv")?.error_for_status()?)
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetcher.fetch("https://digitalocean.com/geo/google.csv")
 }
 
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
     let mut csv_reader = csv::ReaderBuilder::new()
         .has_headers(false)
         .flexible(true)
         .from_reader(reader);
-    csv_reader
+    let ranges = csv_reader
         .records()
         .map(|record| {
             let record = record?;
@@ -82,5 +83,6 @@ pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>,
 
             Ok(RangesWithMetadata::new(metadata, vec![cidr_range]))
         })
-        .collect()
+        .collect::<Result<_, Error>>()?;
+    Ok(CloudRanges::new(ranges))
 }
@@ -1,4 +1,5 @@
-use crate::utils::filter_select::RangesWithMetadata;
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
 use anyhow::Error;
 use libnetrangemerge::IpRange;
 use serde::Deserialize;
@@ -21,11 +22,11 @@ struct FastlyRanges {
     ipv6_addresses: Vec<String>,
 }
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    Ok(reqwest::blocking::get("https://api.fastly.com/public-ip-list")?.error_for_status()?)
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetcher.fetch("https://api.fastly.com/public-ip-list")
 }
 
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
     let mut data = String::new();
     reader.read_to_string(&mut data)?;
     let ranges: FastlyRanges = serde_json::from_str(&data)?;
@@ -35,5 +36,8 @@ pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>,
         .chain(ranges.ipv6_addresses.into_iter())
         .map(|range| Ok(IpRange::from_str(&range)?))
         .collect::<Result<Vec<IpRange>, Error>>()?;
-    Ok(vec![RangesWithMetadata::new(HashMap::new(), ip_ranges)])
+    Ok(CloudRanges::new(vec![RangesWithMetadata::new(
+        HashMap::new(),
+        ip_ranges,
+    )]))
 }
@@ -1,4 +1,6 @@
-use crate::utils::filter_select::RangesWithMetadata;
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
+use crate::utils::timestamp::parse_timestamp;
 use anyhow::{bail, Error};
 use libnetrangemerge::IpRange;
 use serde::Deserialize;
@@ -12,13 +14,16 @@ The GCP service has the following filterable values:
   * is_ipv6 (boolean) - False for IPV4 ranges, True for IPV6 ranges
   * service (string) - The service, always "Google Cloud"
   * scope (string) - The scope, for example: "us-east1" or "europe-central2"
+  * syncToken (string) - The publication time of the file, in milliseconds
+    since the Unix epoch. The same for every range.
+  * creationTime (string) - The publication time of the file, for example:
+    "2021-03-03T12:00:00.000". The same for every range.
 
 Documentation is available from: https://support.google.com/a/answer/10026322?hl=en
 "###;
 
 #[derive(Deserialize, Debug)]
 #[allow(non_snake_case)]
-#[allow(dead_code)]
 struct GcpRanges {
     syncToken: String,
     creationTime: String,
@@ -35,18 +40,23 @@ struct GcpRange {
     scope: String,
 }
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    Ok(
-        reqwest::blocking::get("https://www.gstatic.com/ipranges/cloud.json")?
-            .error_for_status()?,
-    )
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    fetcher.fetch("https://www.gstatic.com/ipranges/cloud.json")
 }
 
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
     let mut data = String::new();
     reader.read_to_string(&mut data)?;
     let ranges: GcpRanges = serde_json::from_str(&data)?;
-    ranges
+
+    // The creation time is in UTC, but without a time zone. One that can't
+    // be parsed only matters to --max-source-age
+    let created_at = parse_timestamp(&ranges.creationTime).ok();
+    let mut file_metadata = HashMap::new();
+    file_metadata.insert("syncToken", ranges.syncToken.into());
+    file_metadata.insert("creationTime", ranges.creationTime.into());
+
+    let ranges = ranges
         .prefixes
         .into_iter()
         .map(|range| {
@@ -63,5 +73,10 @@ pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>,
             };
             Ok(RangesWithMetadata::new(metadata, ranges))
         })
-        .collect()
+        .collect::<Result<_, Error>>()?;
+    Ok(CloudRanges {
+        file_metadata,
+        created_at,
+        ranges,
+    })
 }
@@ -1,7 +1,10 @@
-use crate::utils::filter_select::RangesWithMetadata;
-use anyhow::Error;
+use crate::utils::fetch::{Fetcher, RateLimitError};
+use crate::utils::filter_select::{CloudRanges, RangesWithMetadata};
+use crate::CacheOptions;
+use anyhow::{anyhow, bail, Error};
 use libnetrangemerge::IpRange;
-use serde::Deserialize;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::io;
 use std::str::FromStr;
@@ -10,72 +13,182 @@ pub const FILTER_HELP: &'static str = r###"
 GitHub has the following filterable values:
   * is_ipv4 (boolean) - True for IPV4 ranges, False for IPV6 ranges
   * is_ipv6 (boolean) - False for IPV4 ranges, True for IPV6 ranges
-  * sevice (string) - The service the range is used for, one of:
-    - "hooks"
-    - "web"
-    - "api"
-    - "git"
-    - "pages"
-    - "importer"
-    - "actions"
-    - "dependabot"
+  * service (string) - The service the range is used for, which is the name
+    of the list that it's in, such as "hooks", "web", "api", "git", "pages",
+    "importer", "actions" or "dependabot". GitHub adds new lists over time.
+    Use `--file <file>` to list the services in a range file.
 
 Documentation is available from:
 https://docs.github.com/en/github/authenticating-to-github/about-githubs-ip-addresses
 "###;
 
-#[derive(Deserialize, Debug)]
-struct GithubRanges {
-    hooks: Vec<String>,
-    web: Vec<String>,
-    api: Vec<String>,
-    git: Vec<String>,
-    pages: Vec<String>,
-    importer: Vec<String>,
-    actions: Vec<String>,
-    dependabot: Vec<String>,
+/// Parse the lists of ranges in GitHub's meta file, keyed by the service
+/// that they're used for. A list is told apart by its values: lists without
+/// any ranges, such as the lists of SSH keys, are skipped, while a list with
+/// both ranges and other values is an error. Empty lists are kept, since
+/// GitHub publishes services that currently have no ranges, such as "pages".
+fn parse_services(reader: &mut dyn io::Read) -> Result<Vec<(String, Vec<IpRange>)>, Error> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    let meta: Map<String, Value> = serde_json::from_str(&data)?;
+
+    let mut services = Vec::new();
+    for (service, value) in meta {
+        let values = match value {
+            Value::Array(values) => values,
+            _ => continue,
+        };
+        let ranges: Vec<IpRange> = values
+            .iter()
+            .filter_map(|v| v.as_str().and_then(|r| IpRange::from_str(r).ok()))
+            .collect();
+        if ranges.is_empty() && !values.is_empty() {
+            continue;
+        }
+        if ranges.len() != values.len() {
+            bail!(
+                "The {} list in GitHub's meta file has both ranges and values that \
+                 aren't ranges",
+                service
+            );
+        }
+        services.push((service, ranges));
+    }
+    if services.is_empty() {
+        bail!("No lists of ranges found in GitHub's meta file");
+    }
+    Ok(services)
 }
 
-pub fn fetch_ranges() -> Result<reqwest::blocking::Response, Error> {
-    // reqwest doesn't seem to send a User-Agent header by default,
-    // which causes GitHub to fail our request with a 403. GitHub doesn't
-    // seem to care what User-Agent we use, however.
-    let client = reqwest::blocking::ClientBuilder::new()
-        .user_agent("netrange")
-        .build()?;
-    Ok(client
-        .get("https://api.github.com/meta")
-        .send()?
-        .error_for_status()?)
+/// Describe the filterable values of the ranges in a GitHub meta file,
+/// listing the services that were found in it.
+pub fn filter_help(reader: &mut dyn io::Read) -> Result<String, Error> {
+    let services: Vec<String> = parse_services(reader)?
+        .into_iter()
+        .map(|(service, _)| format!("    - \"{}\"\n", service))
+        .collect();
+    Ok(format!(
+        r###"
+GitHub has the following filterable values:
+  * is_ipv4 (boolean) - True for IPV4 ranges, False for IPV6 ranges
+  * is_ipv6 (boolean) - False for IPV4 ranges, True for IPV6 ranges
+  * service (string) - The service the range is used for, one of:
+{}
+Documentation is available from:
+https://docs.github.com/en/github/authenticating-to-github/about-githubs-ip-addresses
+"###,
+        services.concat()
+    ))
 }
 
-pub fn load_ranges(reader: &mut dyn io::Read) -> Result<Vec<RangesWithMetadata>, Error> {
-    let mut data = String::new();
-    reader.read_to_string(&mut data)?;
-    let github_ranges: GithubRanges = serde_json::from_str(&data)?;
+/// The URL of the meta endpoint of the GitHub API at `base_url`, either
+/// github.com's or that of a GitHub Enterprise Server.
+fn meta_url(base_url: Option<&str>) -> String {
+    match base_url.map(|u| u.trim_end_matches('/')) {
+        None => "https://api.github.com/meta".to_string(),
+        Some(base_url) if base_url.ends_with("/api/v3") => format!("{}/meta", base_url),
+        Some(base_url) => format!("{}/api/v3/meta", base_url),
+    }
+}
 
-    fn parse_ranges(
-        service: &str,
-        unparsed_ranges: Vec<String>,
-    ) -> Result<RangesWithMetadata, Error> {
-        let ranges = unparsed_ranges
-            .into_iter()
-            .map(|r| Ok(IpRange::from_str(&r)?))
-            .collect::<Result<Vec<IpRange>, Error>>()?;
-        let mut metadata = HashMap::new();
-        metadata.insert("service", service.to_string().into());
-        Ok(RangesWithMetadata::new(metadata, ranges))
+/// The URL of the meta endpoint of the GitHub Enterprise Server given
+/// with "--github-url", if any.
+pub fn source_url(options: &CacheOptions) -> Option<String> {
+    options
+        .github
+        .github_url
+        .as_deref()
+        .map(|base_url| meta_url(Some(base_url)))
+}
+
+pub fn fetch_ranges(fetcher: &Fetcher) -> Result<Vec<u8>, Error> {
+    let options = fetcher.github_options();
+    let mut headers = HeaderMap::new();
+    if let Some(token) = options.github_token.as_ref().filter(|t| !t.is_empty()) {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| anyhow!("Invalid GitHub token"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    let authenticated = !headers.is_empty();
+    fetcher
+        .fetch_with_headers(&meta_url(options.github_url.as_deref()), headers)
+        .map_err(|err| match err.downcast_ref::<RateLimitError>() {
+            Some(_) if !authenticated => err.context(
+                "The GitHub API rate limit for anonymous requests was exceeded. \
+                 Use --github-token or GITHUB_TOKEN to authenticate requests",
+            ),
+            _ => err,
+        })
+}
+
+pub fn load_ranges(reader: &mut dyn io::Read) -> Result<CloudRanges, Error> {
+    let ranges_with_metadata = parse_services(reader)?
+        .into_iter()
+        .map(|(service, ranges)| {
+            let mut metadata = HashMap::new();
+            metadata.insert("service", service.into());
+            RangesWithMetadata::new(metadata, ranges)
+        })
+        .collect();
+
+    Ok(CloudRanges::new(ranges_with_metadata))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{filter_help, load_ranges, meta_url, parse_services};
+
+    #[test]
+    fn test_meta_url() {
+        assert_eq!(meta_url(None), "https://api.github.com/meta");
+        assert_eq!(
+            meta_url(Some("https://github.example.com")),
+            "https://github.example.com/api/v3/meta"
+        );
+        assert_eq!(
+            meta_url(Some("https://github.example.com/")),
+            "https://github.example.com/api/v3/meta"
+        );
+        assert_eq!(
+            meta_url(Some("https://github.example.com/api/v3")),
+            "https://github.example.com/api/v3/meta"
+        );
     }
 
-    let mut ranges_with_metadata = Vec::new();
-    ranges_with_metadata.push(parse_ranges("hooks", github_ranges.hooks)?);
-    ranges_with_metadata.push(parse_ranges("web", github_ranges.web)?);
-    ranges_with_metadata.push(parse_ranges("api", github_ranges.api)?);
-    ranges_with_metadata.push(parse_ranges("git", github_ranges.git)?);
-    ranges_with_metadata.push(parse_ranges("pages", github_ranges.pages)?);
-    ranges_with_metadata.push(parse_ranges("importer", github_ranges.importer)?);
-    ranges_with_metadata.push(parse_ranges("actions", github_ranges.actions)?);
-    ranges_with_metadata.push(parse_ranges("dependabot", github_ranges.dependabot)?);
-
-    Ok(ranges_with_metadata)
+    const META: &str = r#"{
+        "verifiable_password_authentication": false,
+        "ssh_key_fingerprints": {"SHA256_RSA": "abc"},
+        "ssh_keys": ["ssh-ed25519 AAAA"],
+        "hooks": ["192.30.252.0/22", "2a0a:a440::/29"],
+        "packages": ["140.82.121.33/32"],
+        "pages": [],
+        "domains": {"website": ["*.github.com"]}
+    }"#;
+
+    #[test]
+    fn test_load_ranges() {
+        let services: Vec<(String, usize)> = parse_services(&mut META.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|(service, ranges)| (service, ranges.len()))
+            .collect();
+        assert_eq!(
+            services,
+            vec![
+                ("hooks".to_string(), 2),
+                ("packages".to_string(), 1),
+                ("pages".to_string(), 0)
+            ]
+        );
+        assert_eq!(load_ranges(&mut META.as_bytes()).unwrap().ranges.len(), 3);
+
+        let help = filter_help(&mut META.as_bytes()).unwrap();
+        assert!(help.contains("- \"hooks\"\n    - \"packages\"\n    - \"pages\"\n"));
+        assert!(!help.contains("ssh_keys"));
+
+        assert!(load_ranges(&mut r#"{"ssh_keys": ["ssh-ed25519 AAAA"]}"#.as_bytes()).is_err());
+        let mixed = r#"{"hooks": ["192.30.252.0/22", "not a range"]}"#;
+        assert!(load_ranges(&mut mixed.as_bytes()).is_err());
+    }
 }
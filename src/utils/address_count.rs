@@ -0,0 +1,115 @@
+use libnetrangemerge::{IpRange, Range as _};
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+
+/// An exact number of addresses. The ipv6 address space contains 2^128
+/// addresses which is one more than fits into a `u128`, so an extra
+/// bit is kept for the case where every address is counted.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct AddressCount {
+    full: bool,
+    count: u128,
+}
+
+impl AddressCount {
+    pub fn add(self, count: u128) -> AddressCount {
+        match self.count.checked_add(count) {
+            Some(count) => AddressCount {
+                full: self.full,
+                count,
+            },
+            None => AddressCount {
+                full: true,
+                count: count - (u128::MAX - self.count) - 1,
+            },
+        }
+    }
+
+    pub fn saturating_sub(self, other: AddressCount) -> AddressCount {
+        if other >= self {
+            return AddressCount::default();
+        }
+        if other.count == 0 && !other.full {
+            return self;
+        }
+        match (self.full, other.full) {
+            (false, _) | (true, true) => AddressCount {
+                full: false,
+                count: self.count - other.count,
+            },
+            // self is at least 2^128 and other is less than that
+            (true, false) => AddressCount {
+                full: false,
+                count: (u128::MAX - other.count) + self.count + 1,
+            },
+        }
+    }
+}
+
+impl From<u128> for AddressCount {
+    fn from(count: u128) -> AddressCount {
+        AddressCount { full: false, count }
+    }
+}
+
+impl Display for AddressCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.full {
+            // A family never has more than 2^128 addresses, so this
+            // is the only value that can have the extra bit set.
+            f.pad("340282366920938463463374607431768211456")
+        } else {
+            f.pad(&self.count.to_string())
+        }
+    }
+}
+
+/// Return the first and last address of `range` as integers
+/// along with whether the range is ipv6.
+pub fn range_bounds(range: &IpRange) -> (bool, u128, u128) {
+    let (is_ipv6, first, bits) = match range.host_address() {
+        IpAddr::V4(addr) => (false, u32::from(*addr) as u128, 32),
+        IpAddr::V6(addr) => (true, u128::from(*addr), 128),
+    };
+    let host_bits = bits - range.prefix_length() as u32;
+    let host_mask = if host_bits == 128 {
+        u128::MAX
+    } else {
+        (1u128 << host_bits) - 1
+    };
+    (is_ipv6, first, first | host_mask)
+}
+
+/// Count the addresses in `intervals`, counting addresses that are in
+/// multiple intervals only once.
+pub fn count_union(mut intervals: Vec<(u128, u128)>) -> AddressCount {
+    intervals.sort_unstable();
+    let mut total = AddressCount::default();
+    let mut current: Option<(u128, u128)> = None;
+    for (first, last) in intervals {
+        current = match current {
+            Some((cur_first, cur_last)) if first <= cur_last => {
+                Some((cur_first, cur_last.max(last)))
+            }
+            Some((cur_first, cur_last)) => {
+                total = total.add(cur_last - cur_first).add(1);
+                Some((first, last))
+            }
+            None => Some((first, last)),
+        };
+    }
+    if let Some((cur_first, cur_last)) = current {
+        total = total.add(cur_last - cur_first).add(1);
+    }
+    total
+}
+
+/// Count the addresses from `first` to `last` that are in any of `intervals`.
+pub fn count_covered(first: u128, last: u128, intervals: &[(u128, u128)]) -> AddressCount {
+    let clipped = intervals
+        .iter()
+        .filter(|(f, l)| *f <= last && *l >= first)
+        .map(|(f, l)| ((*f).max(first), (*l).min(last)))
+        .collect();
+    count_union(clipped)
+}
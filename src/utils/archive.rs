@@ -0,0 +1,269 @@
+use crate::utils::timestamp::format_timestamp;
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// An entry in the index of the archived range files of a service.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// When the file was first downloaded, in seconds since the Unix epoch.
+    pub fetched_at: u64,
+    /// The URL that the file was downloaded from, if it wasn't the
+    /// service's usual one, such as a GitHub Enterprise Server's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// When the service says that it created the file, for services whose
+    /// files say so, such as AWS's "createDate".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// The SHA-256 digest of the file, which is also the name
+    /// that it is stored under.
+    pub sha256: String,
+    /// The top-level values of the file that describe it, such as
+    /// AWS's "syncToken" and "createDate" or Azure's "changeNumber".
+    pub provider_metadata: Map<String, Value>,
+}
+
+impl Snapshot {
+    /// The time from which the file is current until the next snapshot's:
+    /// when the service created it if known, otherwise when it was
+    /// first downloaded.
+    pub fn current_from(&self) -> u64 {
+        self.created_at.unwrap_or(self.fetched_at)
+    }
+}
+
+/// A content-addressed store of every version of the range files that have
+/// been downloaded. Files are stored under "objects/" named by their
+/// SHA-256 digest, and each service has an index under "index/", with
+/// one snapshot per line, of the files that it has served over time.
+pub struct Archive {
+    dir: PathBuf,
+}
+
+/// Get the values from the top level of a JSON range file that aren't
+/// lists or objects. These are the values that providers use to identify
+/// a version of their file. Files that aren't JSON have none.
+fn provider_metadata(body: &[u8]) -> Map<String, Value> {
+    match serde_json::from_slice(body) {
+        Ok(Value::Object(map)) => map
+            .into_iter()
+            .filter(|(_, v)| !v.is_array() && !v.is_object())
+            .collect(),
+        _ => Map::new(),
+    }
+}
+
+impl Archive {
+    pub fn new(dir: &Path) -> Archive {
+        Archive {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join("objects").join(sha256)
+    }
+
+    fn index_path(&self, service: &str) -> PathBuf {
+        self.dir.join("index").join(format!("{}.ndjson", service))
+    }
+
+    /// Read the snapshots of the named service, oldest first.
+    pub fn snapshots(&self, service: &str) -> Result<Vec<Snapshot>, Error> {
+        let index_path = self.index_path(service);
+        if !index_path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = Vec::new();
+        for line in BufReader::new(File::open(&index_path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                snapshots.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// Add a range file downloaded from `url`, or from the service's usual
+    /// URL if it is `None`, to the archive along with when the service
+    /// created it, if known. A new snapshot is only recorded if the file
+    /// differs from the most recent one from the same URL.
+    pub fn add(
+        &self,
+        service: &str,
+        url: Option<&str>,
+        body: &[u8],
+        fetched_at: u64,
+        created_at: Option<u64>,
+    ) -> Result<(), Error> {
+        let sha256: String = Sha256::digest(body)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let snapshots = self.snapshots(service)?;
+        let latest = snapshots.iter().rev().find(|s| s.url.as_deref() == url);
+        if latest.map(|s| &s.sha256) == Some(&sha256) {
+            return Ok(());
+        }
+
+        let object_path = self.object_path(&sha256);
+        if !object_path.exists() {
+            fs::create_dir_all(self.dir.join("objects"))?;
+            let tmp_path = object_path.with_extension("tmp");
+            File::create(&tmp_path)?.write_all(body)?;
+            fs::rename(&tmp_path, &object_path)?;
+        }
+
+        let snapshot = Snapshot {
+            fetched_at,
+            url: url.map(|url| url.to_string()),
+            created_at,
+            sha256,
+            provider_metadata: provider_metadata(body),
+        };
+        fs::create_dir_all(self.dir.join("index"))?;
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.index_path(service))?;
+        writeln!(index, "{}", serde_json::to_string(&snapshot)?)?;
+        Ok(())
+    }
+
+    /// Read the range file of the named service downloaded from `url`, or from
+    /// the service's usual URL if it is `None`, that was current at `as_of`,
+    /// going by when the service created each file where it says so.
+    pub fn read_as_of(
+        &self,
+        service: &str,
+        url: Option<&str>,
+        as_of: u64,
+    ) -> Result<Vec<u8>, Error> {
+        let snapshots: Vec<_> = self
+            .snapshots(service)?
+            .into_iter()
+            .filter(|s| s.url.as_deref() == url)
+            .collect();
+        let snapshot = snapshots
+            .iter()
+            .filter(|s| s.current_from() <= as_of)
+            .max_by_key(|s| s.current_from())
+            .ok_or_else(|| match snapshots.iter().map(|s| s.current_from()).min() {
+                Some(earliest) => anyhow!(
+                    "No archived range file for {} as of {}. The earliest is from {}",
+                    service,
+                    format_timestamp(as_of),
+                    format_timestamp(earliest)
+                ),
+                None => anyhow!("No archived range files for {}", service),
+            })?;
+        let mut body = Vec::new();
+        File::open(self.object_path(&snapshot.sha256))?.read_to_end(&mut body)?;
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Archive;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive() {
+        let dir = TempDir::new().unwrap();
+        let archive = Archive::new(dir.path());
+
+        assert!(archive.read_as_of("aws", None, 100).is_err());
+
+        archive
+            .add(
+                "aws",
+                None,
+                br#"{"syncToken": "1", "prefixes": []}"#,
+                100,
+                None,
+            )
+            .unwrap();
+        archive
+            .add(
+                "aws",
+                None,
+                br#"{"syncToken": "1", "prefixes": []}"#,
+                200,
+                None,
+            )
+            .unwrap();
+        archive
+            .add(
+                "aws",
+                None,
+                br#"{"syncToken": "2", "prefixes": []}"#,
+                300,
+                None,
+            )
+            .unwrap();
+
+        let snapshots = archive.snapshots("aws").unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].fetched_at, 100);
+        assert_eq!(snapshots[0].provider_metadata["syncToken"], "1");
+        assert!(!snapshots[0].provider_metadata.contains_key("prefixes"));
+
+        assert!(archive.read_as_of("aws", None, 99).is_err());
+        assert_eq!(
+            archive.read_as_of("aws", None, 299).unwrap(),
+            br#"{"syncToken": "1", "prefixes": []}"#.to_vec()
+        );
+        assert_eq!(
+            archive.read_as_of("aws", None, 300).unwrap(),
+            br#"{"syncToken": "2", "prefixes": []}"#.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_archive_created_at() {
+        let dir = TempDir::new().unwrap();
+        let archive = Archive::new(dir.path());
+
+        // The first file was downloaded long after it was created, while
+        // the second one was created before it was downloaded
+        archive
+            .add("aws", None, br#"{"syncToken": "1"}"#, 1000, Some(100))
+            .unwrap();
+        archive
+            .add("aws", None, br#"{"syncToken": "2"}"#, 2000, Some(1500))
+            .unwrap();
+        archive
+            .add("gcp", None, br#"{"syncToken": "3"}"#, 3000, None)
+            .unwrap();
+
+        let snapshots = archive.snapshots("aws").unwrap();
+        assert_eq!(snapshots[0].created_at, Some(100));
+        assert!(archive.read_as_of("aws", None, 99).is_err());
+        assert_eq!(
+            archive.read_as_of("aws", None, 500).unwrap(),
+            br#"{"syncToken": "1"}"#.to_vec()
+        );
+        assert_eq!(
+            archive.read_as_of("aws", None, 1500).unwrap(),
+            br#"{"syncToken": "2"}"#.to_vec()
+        );
+        assert!(archive.read_as_of("gcp", None, 2999).is_err());
+        assert!(archive.read_as_of("gcp", None, 3000).is_ok());
+
+        // Files from other URLs are kept apart, even if they are the same
+        let url = Some("https://github.example.com/api/v3/meta");
+        assert!(archive.read_as_of("gcp", url, 3000).is_err());
+        archive
+            .add("gcp", url, br#"{"syncToken": "3"}"#, 4000, None)
+            .unwrap();
+        assert_eq!(archive.snapshots("gcp").unwrap().len(), 2);
+        assert!(archive.read_as_of("gcp", url, 3999).is_err());
+        assert!(archive.read_as_of("gcp", url, 4000).is_ok());
+    }
+}
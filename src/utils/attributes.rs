@@ -0,0 +1,105 @@
+use crate::utils::filter_select::{MetadataValue, RangeWithMetadata};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The types and distinct values that an attribute has.
+#[derive(Default)]
+pub struct AttributeValues {
+    pub types: BTreeSet<&'static str>,
+    /// The number of ranges with each value. Table values are not
+    /// counted here, instead their entries are counted as separate
+    /// attributes.
+    pub values: BTreeMap<MetadataValue, usize>,
+}
+
+fn type_name(value: &MetadataValue) -> &'static str {
+    match value {
+        MetadataValue::Boolean(_) => "boolean",
+        MetadataValue::String(_) => "string",
+        MetadataValue::I64(_) => "integer",
+        MetadataValue::Table(_) => "table",
+    }
+}
+
+fn add_value(
+    attributes: &mut BTreeMap<String, AttributeValues>,
+    name: String,
+    value: &MetadataValue,
+) {
+    if let MetadataValue::Table(table) = value {
+        // Tables with only integer keys are lists, so all of their
+        // entries are counted together.
+        let is_list = table.keys().all(|k| matches!(k, MetadataValue::I64(_)));
+        for (key, entry) in table {
+            let entry_name = if is_list {
+                format!("{}[]", name)
+            } else {
+                format!("{}.{}", name, key)
+            };
+            add_value(attributes, entry_name, entry);
+        }
+        attributes.entry(name).or_default().types.insert("table");
+    } else {
+        let attribute = attributes.entry(name).or_default();
+        attribute.types.insert(type_name(value));
+        *attribute.values.entry(value.clone()).or_default() += 1;
+    }
+}
+
+/// Collect every attribute of `ranges` along with the distinct values
+/// that each has. The entries of table values are included as attributes
+/// named `<attribute>[]` for lists and `<attribute>.<key>` otherwise.
+pub fn collect_attributes(ranges: &[RangeWithMetadata]) -> BTreeMap<String, AttributeValues> {
+    let mut attributes = BTreeMap::new();
+    for range in ranges {
+        for (key, value) in range.metadata.iter() {
+            add_value(&mut attributes, key.to_string(), value);
+        }
+    }
+    attributes
+}
+
+pub fn attributes_to_text(attributes: &BTreeMap<String, AttributeValues>) -> String {
+    let mut text = String::new();
+    for (name, attribute) in attributes {
+        let types: Vec<&str> = attribute.types.iter().copied().collect();
+        text.push_str(&format!("{} ({})\n", name, types.join(", ")));
+        let values: Vec<(String, String)> = attribute
+            .values
+            .iter()
+            .map(|(value, count)| match value {
+                MetadataValue::String(s) if s.is_empty() => ("\"\"".to_string(), count.to_string()),
+                _ => (value.to_string(), count.to_string()),
+            })
+            .collect();
+        let width = values.iter().map(|(v, _)| v.len()).max().unwrap_or(0);
+        for (value, count) in values {
+            text.push_str(&format!(
+                "    {:<width$}  {}\n",
+                value,
+                count,
+                width = width
+            ));
+        }
+    }
+    text
+}
+
+pub fn attributes_to_json(attributes: &BTreeMap<String, AttributeValues>) -> Value {
+    let attributes: Map<String, Value> = attributes
+        .iter()
+        .map(|(name, attribute)| {
+            let values: Vec<Value> = attribute
+                .values
+                .iter()
+                .map(|(value, count)| json!({"value": value, "count": count}))
+                .collect();
+            let value = json!({
+                "types": attribute.types,
+                "values": values,
+            });
+            (name.clone(), value)
+        })
+        .collect();
+    Value::Object(attributes)
+}
@@ -1,15 +1,30 @@
 use crate::sources::{aws, azure, cloudflare, digitalocean, fastly, gcp, github, google};
-use crate::utils::filter_select::RangesWithMetadata;
+use crate::utils::fetch::Fetcher;
+use crate::utils::filter_select::CloudRanges;
+use crate::CacheOptions;
 use anyhow::{bail, Error};
 use once_cell::sync::Lazy;
 use std::io::Read;
 use std::ops::Deref;
 
+type FilterHelpFunc = fn(&mut dyn Read) -> Result<String, Error>;
+type SourceUrlFunc = fn(&CacheOptions) -> Option<String>;
+
 pub struct CloudConfig {
     service_name: &'static str,
-    pub fetch_ranges_func: fn() -> Result<reqwest::blocking::Response, Error>,
-    pub load_ranges_func: fn(&mut dyn Read) -> Result<Vec<RangesWithMetadata>, Error>,
+    pub fetch_ranges_func: fn(&Fetcher) -> Result<Vec<u8>, Error>,
+    pub load_ranges_func: fn(&mut dyn Read) -> Result<CloudRanges, Error>,
     pub filter_help: &'static str,
+    /// Describe the filterable values found in a range file, for services
+    /// whose values change over time.
+    pub filter_help_func: Option<FilterHelpFunc>,
+    /// Return the URL of the range file when it isn't the service's usual
+    /// one because of the options, such as for a GitHub Enterprise Server.
+    /// Files from different URLs are cached and archived separately.
+    pub source_url_func: Option<SourceUrlFunc>,
+    /// The extension of the service's range file, used to name
+    /// the file in a mirror directory.
+    pub file_extension: &'static str,
 }
 
 const CONFIG: &[CloudConfig] = &[
@@ -18,48 +33,99 @@ const CONFIG: &[CloudConfig] = &[
         fetch_ranges_func: aws::fetch_ranges,
         load_ranges_func: aws::load_ranges,
         filter_help: aws::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
     },
     CloudConfig {
         service_name: "azure",
         fetch_ranges_func: azure::fetch_ranges,
         load_ranges_func: azure::load_ranges,
         filter_help: azure::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
+    },
+    CloudConfig {
+        service_name: "azure-government",
+        fetch_ranges_func: azure::fetch_government_ranges,
+        load_ranges_func: azure::load_ranges,
+        filter_help: azure::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
+    },
+    CloudConfig {
+        service_name: "azure-china",
+        fetch_ranges_func: azure::fetch_china_ranges,
+        load_ranges_func: azure::load_ranges,
+        filter_help: azure::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
+    },
+    CloudConfig {
+        service_name: "azure-germany",
+        fetch_ranges_func: azure::fetch_germany_ranges,
+        load_ranges_func: azure::load_ranges,
+        filter_help: azure::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
     },
     CloudConfig {
         service_name: "cloudflare",
         fetch_ranges_func: cloudflare::fetch_ranges,
         load_ranges_func: cloudflare::load_ranges,
         filter_help: cloudflare::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
     },
     CloudConfig {
         service_name: "digitalocean",
         fetch_ranges_func: digitalocean::fetch_ranges,
         load_ranges_func: digitalocean::load_ranges,
         filter_help: digitalocean::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "csv",
     },
     CloudConfig {
         service_name: "fastly",
         fetch_ranges_func: fastly::fetch_ranges,
         load_ranges_func: fastly::load_ranges,
         filter_help: fastly::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
     },
     CloudConfig {
         service_name: "gcp",
         fetch_ranges_func: gcp::fetch_ranges,
         load_ranges_func: gcp::load_ranges,
         filter_help: gcp::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
     },
     CloudConfig {
         service_name: "github",
         fetch_ranges_func: github::fetch_ranges,
         load_ranges_func: github::load_ranges,
         filter_help: github::FILTER_HELP,
+        filter_help_func: Some(github::filter_help),
+        source_url_func: Some(github::source_url),
+        file_extension: "json",
     },
     CloudConfig {
         service_name: "google",
         fetch_ranges_func: google::fetch_ranges,
         load_ranges_func: google::load_ranges,
         filter_help: google::FILTER_HELP,
+        filter_help_func: None,
+        source_url_func: None,
+        file_extension: "json",
     },
 ];
 
@@ -1,14 +1,19 @@
+use crate::formats::{OutputContext, OutputRange};
+use crate::utils::collect_metadata::{collect_merged_metadata, collect_metadata};
 use crate::utils::expand_ranges::expand_ranges;
-use crate::utils::filter_select::{filter_select, RangesWithMetadata};
+use crate::utils::filter_select::{filter_select, CloudRanges, RangeWithMetadata};
 use crate::utils::load_ranges::read_single_line_ranges;
-use anyhow::Error;
-use libnetrangemerge::merge_ranges;
-use std::fs::File;
-use std::io;
-use std::io::{BufWriter, Read, Write};
+use crate::utils::merge_report::handle_merge_report;
+use crate::utils::write_ranges::{output_file_extension, write_ranges, write_ranges_to_file};
+use crate::{OutputOptions, ReportOptions};
+use anyhow::{bail, Error};
+use libnetrangemerge::{merge_ranges, IpRange, RangeInterest};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
 use std::path::PathBuf;
 
-fn get_program(
+pub fn get_program(
     program: Option<String>,
     program_file: Option<PathBuf>,
 ) -> Result<Option<String>, Error> {
@@ -23,21 +28,55 @@ fn get_program(
     }
 }
 
-pub fn cloud_process_ranges(
-    ranges: Vec<RangesWithMetadata>,
-    filter_program: Option<String>,
-    filter_file: Option<PathBuf>,
-    select_program: Option<String>,
-    select_file: Option<PathBuf>,
-    extra_ranges_files: Vec<PathBuf>,
+/// Turn a metadata value into a name that is safe to use as a file name.
+fn group_file_stem(value: &str) -> String {
+    let stem: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() || stem.chars().all(|c| c == '.') {
+        stem.replace('.', "_") + "_"
+    } else {
+        stem
+    }
+}
+
+/// Split `ranges` into groups by the value of their `group_by` attribute.
+/// Ranges without the attribute are dropped.
+fn group_ranges(
+    ranges: Vec<RangeWithMetadata>,
+    group_by: &str,
+) -> BTreeMap<String, Vec<RangeWithMetadata>> {
+    let mut groups: BTreeMap<String, Vec<RangeWithMetadata>> = BTreeMap::new();
+    for range in ranges {
+        if let Some(value) = range.metadata.get(group_by) {
+            groups.entry(value.to_string()).or_default().push(range);
+        }
+    }
+    groups
+}
+
+fn process_ranges(
+    ranges: Vec<RangeWithMetadata>,
+    extra_ranges: &[RangeInterest<IpRange>],
     min_ipv4_network_size: Option<u8>,
     min_ipv6_network_size: Option<u8>,
     do_merge: bool,
-) -> Result<(), Error> {
-    let filter_program = get_program(filter_program, filter_file)?;
-    let select_program = get_program(select_program, select_file)?;
-    let mut filtered_ranges =
-        filter_select(ranges, filter_program.as_deref(), select_program.as_deref())?;
+    include_unselected: bool,
+) -> (Vec<RangeInterest<IpRange>>, Vec<OutputRange>) {
+    let (mut filtered_ranges, mut metadata): (Vec<_>, Vec<_>) =
+        ranges.into_iter().map(|n| (n.range, n.metadata)).unzip();
+    let input_ranges: Vec<_> = filtered_ranges
+        .iter()
+        .chain(extra_ranges.iter())
+        .copied()
+        .collect();
 
     expand_ranges(
         filtered_ranges.iter_mut().map(|n| n.range_mut()),
@@ -45,26 +84,167 @@ pub fn cloud_process_ranges(
         min_ipv6_network_size,
     );
 
+    filtered_ranges.extend_from_slice(extra_ranges);
+    metadata.resize_with(filtered_ranges.len(), Default::default);
+
+    let output_ranges = if do_merge {
+        let sources: Vec<_> = filtered_ranges
+            .iter()
+            .map(|n| *n.range())
+            .zip(metadata)
+            .collect();
+        merge_ranges(&mut filtered_ranges);
+        collect_merged_metadata(filtered_ranges, &sources)
+    } else {
+        collect_metadata(filtered_ranges, metadata)
+    };
+
+    let output_ranges = output_ranges
+        .into_iter()
+        .filter(|n| include_unselected || n.selected)
+        .collect();
+    (input_ranges, output_ranges)
+}
+
+/// Filter, select, and merge `ranges` the same way as the "cloud merge"
+/// subcommand and return the selected ranges.
+pub fn cloud_merged_ranges(
+    ranges: CloudRanges,
+    filter_program: Option<String>,
+    filter_file: Option<PathBuf>,
+    select_program: Option<String>,
+    select_file: Option<PathBuf>,
+) -> Result<Vec<OutputRange>, Error> {
+    let filter_program = get_program(filter_program, filter_file)?;
+    let select_program = get_program(select_program, select_file)?;
+    let ranges = filter_select(ranges, filter_program.as_deref(), select_program.as_deref())?;
+    let (_, output_ranges) = process_ranges(ranges, &[], None, None, true, false);
+    Ok(output_ranges)
+}
+
+/// How `cloud_process_ranges` filters, selects, merges, and groups ranges,
+/// built from the options of the command being run.
+#[derive(Default)]
+pub struct ProcessOptions {
+    pub filter_program: Option<String>,
+    pub filter_file: Option<PathBuf>,
+    pub select_program: Option<String>,
+    pub select_file: Option<PathBuf>,
+    pub extra_ranges_files: Vec<PathBuf>,
+    pub min_ipv4_network_size: Option<u8>,
+    pub min_ipv6_network_size: Option<u8>,
+    /// `true` to merge the ranges, otherwise they are written as they are.
+    pub merge: bool,
+    pub include_unselected: bool,
+    pub group_by: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub report: Option<ReportOptions>,
+}
+
+pub fn cloud_process_ranges(
+    service: &str,
+    ranges: CloudRanges,
+    options: ProcessOptions,
+    output_options: &OutputOptions,
+) -> Result<(), Error> {
+    let ProcessOptions {
+        filter_program,
+        filter_file,
+        select_program,
+        select_file,
+        extra_ranges_files,
+        min_ipv4_network_size,
+        min_ipv6_network_size,
+        merge: do_merge,
+        include_unselected,
+        group_by,
+        output_dir,
+        report: report_options,
+    } = options;
+    let filter_program = get_program(filter_program, filter_file)?;
+    let select_program = get_program(select_program, select_file)?;
+    let source_metadata = ranges
+        .file_metadata
+        .iter()
+        .map(|(k, v)| (*k, v.clone()))
+        .collect();
+    let ranges = filter_select(ranges, filter_program.as_deref(), select_program.as_deref())?;
+
+    let mut extra_ranges = Vec::new();
     for extra_ranges_file in extra_ranges_files {
         read_single_line_ranges(
             &mut File::open(&extra_ranges_file)?,
-            &mut filtered_ranges,
+            &mut extra_ranges,
             false,
         )?;
     }
 
-    if do_merge {
-        merge_ranges(&mut filtered_ranges);
-    }
+    let context = OutputContext {
+        service: Some(service.to_string()),
+        filter: filter_program,
+        merged: do_merge,
+        chunk: None,
+        source_metadata,
+    };
 
-    let stdout = io::stdout();
-    let mut stdout = BufWriter::new(stdout.lock());
-    for network in filtered_ranges {
-        if network.is_selected() {
-            writeln!(stdout, "{}", network.range())?;
+    if let (Some(group_by), Some(output_dir)) = (&group_by, &output_dir) {
+        if output_options.output_file.is_some() {
+            bail!("--output can't be used with --group-by, use --output-dir instead");
+        }
+        fs::create_dir_all(output_dir)?;
+        let extension = output_file_extension(output_options)?;
+        let mut written_files = HashSet::new();
+        let mut outputs = Vec::new();
+        for (value, group) in group_ranges(ranges, group_by) {
+            let file_name = format!("{}.{}", group_file_stem(&value), extension);
+            if !written_files.insert(file_name.clone()) {
+                bail!(
+                    "Multiple values of {} would be written to the same file: {}",
+                    group_by,
+                    file_name,
+                );
+            }
+            let (input_ranges, output_ranges) = process_ranges(
+                group,
+                &extra_ranges,
+                min_ipv4_network_size,
+                min_ipv6_network_size,
+                do_merge,
+                include_unselected,
+            );
+            if let Some(report_options) = &report_options {
+                let heading = format!("{} = {}", group_by, value);
+                handle_merge_report(
+                    report_options,
+                    &input_ranges,
+                    &output_ranges,
+                    Some(&heading),
+                )?;
+            }
+            outputs.push((file_name, output_ranges));
+        }
+        for (file_name, output_ranges) in outputs {
+            write_ranges_to_file(
+                &output_dir.join(file_name),
+                &output_ranges,
+                output_options,
+                &context,
+            )?;
+        }
+    } else {
+        let (input_ranges, output_ranges) = process_ranges(
+            ranges,
+            &extra_ranges,
+            min_ipv4_network_size,
+            min_ipv6_network_size,
+            do_merge,
+            include_unselected,
+        );
+        if let Some(report_options) = &report_options {
+            handle_merge_report(report_options, &input_ranges, &output_ranges, None)?;
         }
+        write_ranges(&output_ranges, output_options, &context)?;
     }
-    stdout.flush()?;
 
     Ok(())
 }
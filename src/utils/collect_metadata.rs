@@ -0,0 +1,71 @@
+use crate::formats::OutputRange;
+use crate::utils::filter_select::Metadata;
+use libnetrangemerge::{IpRange, Range as _, RangeInterest};
+use std::rc::Rc;
+
+/// Convert ranges into output ranges that have the metadata of the
+/// record at the same index in `metadata`.
+pub fn collect_metadata(
+    ranges: Vec<RangeInterest<IpRange>>,
+    metadata: Vec<Rc<Metadata>>,
+) -> Vec<OutputRange> {
+    ranges
+        .into_iter()
+        .zip(metadata)
+        .map(|(range, metadata)| {
+            let mut output_range = OutputRange::new(range.unwrap(), range.is_selected());
+            add_metadata(&mut output_range, &metadata);
+            output_range
+        })
+        .collect()
+}
+
+/// Convert merged ranges into output ranges that have the union of the metadata
+/// of every source range that was merged into them. Every source range must be
+/// contained by one of the merged ranges, which is always true if `merged` is
+/// the result of merging the source ranges.
+pub fn collect_merged_metadata(
+    merged: Vec<RangeInterest<IpRange>>,
+    sources: &[(IpRange, Rc<Metadata>)],
+) -> Vec<OutputRange> {
+    let mut output_ranges: Vec<OutputRange> = merged
+        .into_iter()
+        .map(|r| OutputRange::new(r.unwrap(), r.is_selected()))
+        .collect();
+
+    // Merged ranges never overlap, so the only merged range that can
+    // contain a source range is the one with the largest host
+    // address that isn't larger than the source range's host address.
+    let mut index: Vec<usize> = (0..output_ranges.len()).collect();
+    index.sort_by_key(|idx| *output_ranges[*idx].range.host_address());
+    for (source, metadata) in sources {
+        let pos = match index.binary_search_by(|idx| {
+            output_ranges[*idx]
+                .range
+                .host_address()
+                .cmp(source.host_address())
+        }) {
+            Ok(pos) => pos + 1,
+            Err(pos) => pos,
+        };
+        if pos == 0 {
+            continue;
+        }
+        let output_range = &mut output_ranges[index[pos - 1]];
+        if output_range.range.is_ipv6() == source.is_ipv6() && output_range.range.contains(source) {
+            add_metadata(output_range, metadata);
+        }
+    }
+
+    output_ranges
+}
+
+fn add_metadata(output_range: &mut OutputRange, metadata: &Metadata) {
+    for (key, value) in metadata {
+        output_range
+            .metadata
+            .entry(key)
+            .or_default()
+            .insert(value.clone());
+    }
+}
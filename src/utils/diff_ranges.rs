@@ -0,0 +1,91 @@
+use crate::utils::address_count::{count_union, range_bounds, AddressCount};
+use crate::utils::filter_select::{filter_select, CloudRanges, MetadataValue};
+use anyhow::Error;
+use libnetrangemerge::{IpRange, Range as _};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+pub type SortedMetadata = BTreeMap<&'static str, MetadataValue>;
+
+/// The differences between the records of two versions of a provider file.
+#[derive(Default)]
+pub struct RecordDiff {
+    pub added: Vec<(IpRange, SortedMetadata)>,
+    pub removed: Vec<(IpRange, SortedMetadata)>,
+    /// Ranges that are in both versions but with different metadata, along
+    /// with the metadata that was removed and the metadata that was added.
+    pub changed: Vec<(IpRange, Vec<SortedMetadata>, Vec<SortedMetadata>)>,
+}
+
+/// Records indexed by their range. `IpRange` isn't ordered, so the
+/// host address and prefix length are used as the key to sort by.
+type RecordIndex = BTreeMap<(IpAddr, u8), (IpRange, BTreeSet<SortedMetadata>)>;
+
+fn index_records(ranges: CloudRanges) -> Result<RecordIndex, Error> {
+    let mut index = RecordIndex::new();
+    for record in filter_select(ranges, None, None)? {
+        let range = *record.range.range();
+        let metadata = record
+            .metadata
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        index
+            .entry((*range.host_address(), range.prefix_length()))
+            .or_insert_with(|| (range, BTreeSet::new()))
+            .1
+            .insert(metadata);
+    }
+    Ok(index)
+}
+
+/// Compare the records of two versions of a provider file. Records are
+/// matched up by their range.
+pub fn diff_records(old: CloudRanges, new: CloudRanges) -> Result<RecordDiff, Error> {
+    let old = index_records(old)?;
+    let mut new = index_records(new)?;
+
+    let mut diff = RecordDiff::default();
+    for (key, (range, old_metadata)) in old {
+        match new.remove(&key).map(|(_, m)| m) {
+            None => diff
+                .removed
+                .extend(old_metadata.into_iter().map(|m| (range, m))),
+            Some(new_metadata) if new_metadata != old_metadata => {
+                let removed = old_metadata.difference(&new_metadata).cloned().collect();
+                let added = new_metadata.difference(&old_metadata).cloned().collect();
+                diff.changed.push((range, removed, added));
+            }
+            Some(_) => {}
+        }
+    }
+    for (_, (range, new_metadata)) in new {
+        diff.added
+            .extend(new_metadata.into_iter().map(|m| (range, m)));
+    }
+    Ok(diff)
+}
+
+/// Count the addresses in `ranges` for each address family.
+pub fn count_addresses(ranges: &[IpRange]) -> (AddressCount, AddressCount) {
+    let mut ipv4 = Vec::new();
+    let mut ipv6 = Vec::new();
+    for range in ranges {
+        let (is_ipv6, first, last) = range_bounds(range);
+        if is_ipv6 {
+            ipv6.push((first, last));
+        } else {
+            ipv4.push((first, last));
+        }
+    }
+    (count_union(ipv4), count_union(ipv6))
+}
+
+/// Format the change from `old` to `new` with a sign.
+pub fn format_delta(old: AddressCount, new: AddressCount) -> String {
+    if new >= old {
+        format!("+{}", new.saturating_sub(old))
+    } else {
+        format!("-{}", old.saturating_sub(new))
+    }
+}
@@ -0,0 +1,473 @@
+use crate::utils::archive::Archive;
+use crate::utils::cloud_config::get_cloud_config;
+use crate::utils::timestamp::format_timestamp;
+use crate::{CacheOptions, GithubOptions, HttpOptions};
+use anyhow::{anyhow, bail, Error};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{Certificate, Proxy, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Information about a cached range file needed to decide if it
+/// is still fresh and to ask the server if it has changed.
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheMetadata {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// When the file was last downloaded or revalidated, in
+    /// seconds since the Unix epoch.
+    fetched_at: u64,
+}
+
+struct CacheEntry {
+    body_path: PathBuf,
+    metadata_path: PathBuf,
+}
+
+impl CacheEntry {
+    /// Create the cache entry of the named service's range file. Files
+    /// downloaded from a URL other than the service's usual one, given by
+    /// `url`, are cached under a name that includes a digest of the URL so that
+    /// they are never mixed up with the service's own file.
+    fn new(cache_dir: &Path, service: &str, url: Option<&str>) -> CacheEntry {
+        let name = match url {
+            Some(url) => {
+                let digest: String = Sha256::digest(url.as_bytes())
+                    .iter()
+                    .take(8)
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                format!("{}-{}", service, digest)
+            }
+            None => service.to_string(),
+        };
+        CacheEntry {
+            body_path: cache_dir.join(format!("{}.body", name)),
+            metadata_path: cache_dir.join(format!("{}.json", name)),
+        }
+    }
+
+    fn read(&self) -> Result<(CacheMetadata, Vec<u8>), Error> {
+        let metadata = serde_json::from_reader(File::open(&self.metadata_path)?)?;
+        let mut body = Vec::new();
+        File::open(&self.body_path)?.read_to_end(&mut body)?;
+        Ok((metadata, body))
+    }
+
+    fn write(&self, metadata: &CacheMetadata, body: Option<&[u8]>) -> Result<(), Error> {
+        if let Some(dir) = self.metadata_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if let Some(body) = body {
+            write_file_atomic(&self.body_path, body)?;
+        }
+        write_file_atomic(&self.metadata_path, &serde_json::to_vec(metadata)?)?;
+        Ok(())
+    }
+}
+
+/// Write `data` to a temporary file and then rename it to `path` so that a
+/// failure part of the way through never leaves a truncated file behind.
+pub fn write_file_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    File::create(&tmp_path)?.write_all(data)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Return the directory to cache range files in if none was specified.
+fn default_cache_dir() -> Result<PathBuf, Error> {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir).join("netrange"));
+    }
+    if cfg!(windows) {
+        if let Some(dir) = env::var_os("LOCALAPPDATA") {
+            return Ok(PathBuf::from(dir).join("netrange"));
+        }
+    }
+    if let Some(home) = env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        if cfg!(target_os = "macos") {
+            return Ok(home.join("Library").join("Caches").join("netrange"));
+        }
+        return Ok(home.join(".cache").join("netrange"));
+    }
+    bail!("Unable to determine a directory to cache range files in. Please use --cache-dir")
+}
+
+/// The error returned when a server refuses a request because the client
+/// has made too many requests, as reported by the de facto standard
+/// X-RateLimit-* headers.
+#[derive(Debug)]
+pub struct RateLimitError {
+    pub url: String,
+    /// When the rate limit resets, in seconds since the Unix epoch.
+    pub reset: Option<u64>,
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limit exceeded for {}", self.url)?;
+        if let Some(reset) = self.reset {
+            write!(f, ". The limit resets at {}", format_timestamp(reset))?;
+            let wait = reset.saturating_sub(now());
+            if wait > 0 {
+                write!(f, " (in {} seconds)", wait)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimitError {}
+
+/// Return an error if `response` reports that the rate limit has been exceeded.
+fn check_rate_limit(url: &str, response: &Response) -> Result<(), RateLimitError> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    if header("x-ratelimit-remaining") == Some(0) {
+        return Err(RateLimitError {
+            url: url.to_string(),
+            reset: header("x-ratelimit-reset"),
+        });
+    }
+    Ok(())
+}
+
+/// The longest time to wait before retrying a request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A `Fetcher` is passed to the fetch function of each service to download
+/// the service's range file. If a cached copy of the file is available, the
+/// server is asked to only send the file if it has changed.
+pub struct Fetcher {
+    client: Client,
+    retries: u32,
+    retry_delay: Duration,
+    github: GithubOptions,
+    /// The URL given with "--url" to download the range file from
+    /// instead of the service's own.
+    url_override: Option<String>,
+    cache_entry: Option<CacheEntry>,
+    cached: Option<(CacheMetadata, Vec<u8>)>,
+}
+
+impl Fetcher {
+    /// Send `request`, retrying with exponential backoff if it fails with a
+    /// connection error, a timeout, or a server error. The response to the
+    /// last attempt is returned even if it is an error response.
+    fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let mut delay = self.retry_delay.min(MAX_RETRY_DELAY);
+        let mut attempt = 0;
+        loop {
+            let result = request
+                .try_clone()
+                .ok_or_else(|| anyhow!("Unable to retry request"))?
+                .send();
+            let retry = match &result {
+                Ok(response) => {
+                    response.status().is_server_error()
+                        || response.status() == StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(err) => err.is_connect() || err.is_timeout(),
+            };
+            if !retry || attempt >= self.retries {
+                return Ok(result?);
+            }
+            match &result {
+                Ok(response) => eprintln!(
+                    "Warning: Request failed with {}, retrying in {} ms",
+                    response.status(),
+                    delay.as_millis()
+                ),
+                Err(err) => eprintln!(
+                    "Warning: Request failed: {}, retrying in {} ms",
+                    err,
+                    delay.as_millis()
+                ),
+            }
+            thread::sleep(delay);
+            delay = delay
+                .checked_mul(2)
+                .unwrap_or(MAX_RETRY_DELAY)
+                .min(MAX_RETRY_DELAY);
+            attempt += 1;
+        }
+    }
+
+    /// Send a GET request for `url` for anything other than the range file
+    /// itself. Failed requests are retried the same way as downloads of the
+    /// range file.
+    pub fn get(&self, url: &str) -> Result<Response, Error> {
+        self.send(self.client.get(url))
+    }
+
+    /// The options for downloading GitHub's range file.
+    pub fn github_options(&self) -> &GithubOptions {
+        &self.github
+    }
+
+    /// The URL to download the range file from instead of the service's
+    /// own, if one was given with "--url".
+    pub fn url_override(&self) -> Option<&str> {
+        self.url_override.as_deref()
+    }
+
+    /// Download the range file from `url`, or from the URL given with "--url"
+    /// if there is one. If the cached copy of the file was downloaded from the
+    /// same URL and the server reports that it hasn't changed, the cached copy
+    /// is returned.
+    pub fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        self.fetch_with_headers(url, HeaderMap::new())
+    }
+
+    /// Download the range file the same way as `fetch`, adding `headers` to
+    /// the request. The headers are sent even if the URL was overridden, so
+    /// that a mirror of an API gets the same credentials as the API itself.
+    pub fn fetch_with_headers(&self, url: &str, headers: HeaderMap) -> Result<Vec<u8>, Error> {
+        let url = self.url_override().unwrap_or(url);
+        let mut request = self.client.get(url).headers(headers);
+        let cached = self.cached.as_ref().filter(|(m, _)| m.url == url);
+        if let Some((metadata, _)) = cached {
+            if let Some(etag) = &metadata.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &metadata.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = self.send(request)?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            let (metadata, body) =
+                cached.ok_or_else(|| anyhow!("Unexpected Not Modified response from {}", url))?;
+            if let Some(cache_entry) = &self.cache_entry {
+                let metadata = CacheMetadata {
+                    url: metadata.url.clone(),
+                    etag: metadata.etag.clone(),
+                    last_modified: metadata.last_modified.clone(),
+                    fetched_at: now(),
+                };
+                cache_entry.write(&metadata, None)?;
+            }
+            return Ok(body.clone());
+        }
+
+        check_rate_limit(url, &response)?;
+        let response = response.error_for_status()?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let metadata = CacheMetadata {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            fetched_at: now(),
+        };
+        let body = response.bytes()?.to_vec();
+        if let Some(cache_entry) = &self.cache_entry {
+            cache_entry.write(&metadata, Some(&body))?;
+        }
+        Ok(body)
+    }
+}
+
+/// Read the certificates from a PEM file that may contain several of them.
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>, Error> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut pem = String::new();
+    File::open(path)
+        .map_err(|e| anyhow!("Unable to open CA bundle {}: {}", path.display(), e))?
+        .read_to_string(&mut pem)?;
+    let certificates = pem
+        .split(END)
+        .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|block| {
+            Ok(Certificate::from_pem(
+                format!("{}{}\n", block, END).as_bytes(),
+            )?)
+        })
+        .collect::<Result<Vec<Certificate>, Error>>()?;
+    if certificates.is_empty() {
+        bail!("No certificates found in CA bundle {}", path.display());
+    }
+    Ok(certificates)
+}
+
+/// Parse the headers to add to every request from `options` and from
+/// the NETRANGE_HTTP_HEADERS environment variable.
+fn parse_headers(options: &HttpOptions) -> Result<HeaderMap, Error> {
+    let env_headers = env::var("NETRANGE_HTTP_HEADERS").unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    for header in env_headers
+        .lines()
+        .filter(|h| !h.trim().is_empty())
+        .chain(options.headers.iter().map(|h| h.as_str()))
+    {
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap().trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| anyhow!("Invalid header, expected <name>: <value>: {}", header))?
+            .trim();
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    Ok(headers)
+}
+
+/// Build the HTTP client used for every request.
+fn build_client(options: &HttpOptions) -> Result<Client, Error> {
+    // reqwest doesn't send a User-Agent header by default, which
+    // causes some services, like GitHub, to fail our requests.
+    let mut builder = Client::builder()
+        .user_agent("netrange")
+        .timeout(Duration::from_secs(options.timeout))
+        .default_headers(parse_headers(options)?);
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    if let Some(ca_bundle) = &options.ca_bundle {
+        for certificate in read_certificates(ca_bundle)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Return the path of the range file of the named service in `mirror_dir`.
+pub fn mirror_file_path(mirror_dir: &Path, service: &str) -> Result<PathBuf, Error> {
+    let cc = get_cloud_config(service)?;
+    Ok(mirror_dir.join(format!("{}.{}", service, cc.file_extension)))
+}
+
+/// Return the URL to download the range file of the named service from if
+/// it was overridden with "--url" or with an environment variable.
+fn url_override(service: &str, options: &CacheOptions) -> Result<Option<String>, Error> {
+    for value in &options.urls {
+        let mut parts = value.splitn(2, '=');
+        let name = parts.next().unwrap();
+        let url = parts
+            .next()
+            .ok_or_else(|| anyhow!("Invalid --url value, expected <service>=<url>: {}", value))?;
+        get_cloud_config(name)?;
+        if name == service {
+            return Ok(Some(url.to_string()));
+        }
+    }
+    // Environment variable names can't contain a "-", so services such
+    // as "azure-china" use "NETRANGE_AZURE_CHINA_URL"
+    let var = format!("NETRANGE_{}_URL", service.to_uppercase().replace('-', "_"));
+    Ok(env::var(var).ok().filter(|url| !url.is_empty()))
+}
+
+/// Get the range file for the named service, from the archive if a time is
+/// given, from the mirror directory if there is one, from the cache if allowed
+/// by `options` or else by downloading it. Downloaded files are added to the
+/// archive if there is one.
+pub fn fetch_cloud_ranges(service: &str, options: &CacheOptions) -> Result<Vec<u8>, Error> {
+    let cc = get_cloud_config(service)?;
+    let url = url_override(service, options)?;
+    // The URL of the file if it isn't the service's usual one, which keeps
+    // files from different URLs apart in the cache and the archive
+    let source_url = url
+        .clone()
+        .or_else(|| cc.source_url_func.and_then(|f| f(options)));
+
+    if let (Some(archive_dir), Some(as_of)) = (&options.archive_dir, options.as_of) {
+        return Archive::new(archive_dir).read_as_of(service, source_url.as_deref(), as_of);
+    }
+
+    if let Some(mirror_dir) = &options.mirror_dir {
+        let path = mirror_file_path(mirror_dir, service)?;
+        let mut body = Vec::new();
+        File::open(&path)
+            .map_err(|e| anyhow!("Unable to open mirrored file {}: {}", path.display(), e))?
+            .read_to_end(&mut body)?;
+        return Ok(body);
+    }
+
+    let cache_entry = if options.no_cache {
+        None
+    } else {
+        let cache_dir = match &options.cache_dir {
+            Some(cache_dir) => cache_dir.clone(),
+            None => default_cache_dir()?,
+        };
+        Some(CacheEntry::new(&cache_dir, service, source_url.as_deref()))
+    };
+    // A cache file that can't be read is treated the same as a missing one
+    let cached = cache_entry.as_ref().and_then(|e| e.read().ok());
+
+    match &cached {
+        Some((metadata, body)) => {
+            let age = now().saturating_sub(metadata.fetched_at);
+            let fresh = match options.max_age {
+                Some(max_age) => age <= max_age,
+                None => false,
+            };
+            if options.offline || fresh {
+                return Ok(body.clone());
+            }
+        }
+        None if options.offline => {
+            bail!("No cached range file for {} is available", service)
+        }
+        None => {}
+    }
+
+    let fetcher = Fetcher {
+        client: build_client(&options.http)?,
+        retries: options.http.retries,
+        retry_delay: Duration::from_millis(options.http.retry_delay),
+        github: options.github.clone(),
+        url_override: url,
+        cache_entry,
+        cached,
+    };
+    let body = (cc.fetch_ranges_func)(&fetcher)?;
+    if let Some(archive_dir) = &options.archive_dir {
+        // The file is checked by the command that loads it, so one that
+        // can't be loaded here is just archived without a creation time
+        let created_at = (cc.load_ranges_func)(&mut &body[..])
+            .ok()
+            .and_then(|ranges| ranges.created_at);
+        Archive::new(archive_dir).add(service, source_url.as_deref(), &body, now(), created_at)?;
+    }
+    Ok(body)
+}
@@ -1,8 +1,11 @@
 use anyhow::{bail, Error};
 use libnetrangemerge::{IpRange, Range as _, RangeInterest};
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
 
-#[derive(Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub enum MetadataValue {
     Boolean(bool),
     String(String),
@@ -34,28 +37,123 @@ impl From<BTreeMap<MetadataValue, MetadataValue>> for MetadataValue {
     }
 }
 
+impl Serialize for MetadataValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MetadataValue::Boolean(val) => serializer.serialize_bool(*val),
+            MetadataValue::String(val) => serializer.serialize_str(val),
+            MetadataValue::I64(val) => serializer.serialize_i64(*val),
+            MetadataValue::Table(val) => {
+                // JSON only supports string keys, so non-string keys
+                // are converted to strings.
+                let mut map = serializer.serialize_map(Some(val.len()))?;
+                for (key, value) in val {
+                    map.serialize_entry(&key.to_string(), value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl Display for MetadataValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataValue::Boolean(val) => write!(f, "{}", val),
+            MetadataValue::String(val) => write!(f, "{}", val),
+            MetadataValue::I64(val) => write!(f, "{}", val),
+            MetadataValue::Table(_) => {
+                let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+                write!(f, "{}", json)
+            }
+        }
+    }
+}
+
+pub type Metadata = HashMap<&'static str, MetadataValue>;
+
+#[derive(Clone)]
 pub struct RangesWithMetadata {
-    metadata: HashMap<&'static str, MetadataValue>,
+    metadata: Metadata,
     ranges: Vec<IpRange>,
 }
 
 impl RangesWithMetadata {
-    pub fn new(
-        metadata: HashMap<&'static str, MetadataValue>,
-        ranges: Vec<IpRange>,
-    ) -> RangesWithMetadata {
+    pub fn new(metadata: Metadata, ranges: Vec<IpRange>) -> RangesWithMetadata {
         RangesWithMetadata { metadata, ranges }
     }
 }
 
-fn build_lua_value(ctx: rlua::Context<'_>, value: MetadataValue) -> Result<rlua::Value<'_>, Error> {
+/// The records of a cloud service's range file along with the
+/// metadata of the file itself.
+#[derive(Clone)]
+pub struct CloudRanges {
+    /// Values that describe the whole file, such as AWS's "syncToken".
+    /// They are available to filter and select programs along with the
+    /// metadata of each record.
+    pub file_metadata: Metadata,
+    /// When the service created the file, in seconds since the Unix
+    /// epoch, if the file says.
+    pub created_at: Option<u64>,
+    pub ranges: Vec<RangesWithMetadata>,
+}
+
+impl CloudRanges {
+    /// Create a `CloudRanges` for a file that has no file-level metadata.
+    pub fn new(ranges: Vec<RangesWithMetadata>) -> CloudRanges {
+        CloudRanges {
+            file_metadata: Metadata::new(),
+            created_at: None,
+            ranges,
+        }
+    }
+
+    /// Add a "provider" attribute naming `service` to every record, so that
+    /// records say which service they came from however they are combined.
+    pub fn with_provider(mut self, service: &str) -> CloudRanges {
+        for ranges in &mut self.ranges {
+            ranges
+                .metadata
+                .insert("provider", service.to_string().into());
+        }
+        self
+    }
+
+    /// Combine the ranges of several services into one set of records. The
+    /// metadata of the files is only kept if there is a single service, since
+    /// different services' files have different values for the same names.
+    pub fn combine(services: Vec<CloudRanges>) -> CloudRanges {
+        let single = services.len() == 1;
+        let mut combined = CloudRanges::new(Vec::new());
+        for cloud_ranges in services {
+            if single {
+                combined.file_metadata = cloud_ranges.file_metadata;
+                combined.created_at = cloud_ranges.created_at;
+            }
+            combined.ranges.extend(cloud_ranges.ranges);
+        }
+        combined
+    }
+}
+
+/// A range that passed filtering along with the metadata
+/// of the record that it came from.
+pub struct RangeWithMetadata {
+    pub range: RangeInterest<IpRange>,
+    pub metadata: Rc<Metadata>,
+}
+
+fn build_lua_value<'lua>(
+    ctx: rlua::Context<'lua>,
+    value: &MetadataValue,
+) -> Result<rlua::Value<'lua>, Error> {
     let out = match value {
-        MetadataValue::Boolean(val) => rlua::Value::Boolean(val),
-        MetadataValue::String(val) => rlua::Value::String(ctx.create_string(&val)?),
-        MetadataValue::I64(val) => rlua::Value::Integer(val),
+        MetadataValue::Boolean(val) => rlua::Value::Boolean(*val),
+        MetadataValue::String(val) => rlua::Value::String(ctx.create_string(val)?),
+        MetadataValue::I64(val) => rlua::Value::Integer(*val),
         MetadataValue::Table(val) => {
             let table = ctx.create_table()?;
-            for (key, value) in val.into_iter() {
+            for (key, value) in val.iter() {
                 let k = build_lua_value(ctx, key)?;
                 let v = build_lua_value(ctx, value)?;
                 table.set(k, v)?;
@@ -66,71 +164,300 @@ fn build_lua_value(ctx: rlua::Context<'_>, value: MetadataValue) -> Result<rlua:
     Ok(out)
 }
 
+/// Lua code that returns a table to use as the environment of the filter
+/// and select programs, along with a function that sets the table that the
+/// environment reads from and writes to. This lets each program be compiled
+/// once and then run with a fresh environment for every range.
+const ENVIRONMENT_PROXY: &str = r#"
+local current = {}
+local proxy = setmetatable({}, {
+    __index = function(_, key) return current[key] end,
+    __newindex = function(_, key, value) current[key] = value end,
+    __metatable = false,
+})
+return proxy, function(env) current = env end
+"#;
+
+/// Lua code that copies the globals, along with the metadata of the file,
+/// into the base environment and returns a function that makes a table fall
+/// back to it. The base environment is only ever seen through read-only
+/// proxies, which are created afresh for every run so that not even `rawset`
+/// can change what later runs see. Strings' methods use the copy of the
+/// string library, so changes to the real one can't affect them either.
+const BASE_ENVIRONMENT: &str = r#"
+local globals, file_metadata = ...
+local copies = {}
+local function copy(value)
+    if type(value) ~= "table" then return value end
+    if copies[value] == nil then
+        copies[value] = {}
+        for k, v in pairs(value) do copies[value][copy(k)] = copy(v) end
+    end
+    return copies[value]
+end
+local base = copy(globals)
+for k, v in pairs(file_metadata) do base[k] = v end
+local string_metatable = getmetatable("")
+string_metatable.__index = base.string
+string_metatable.__metatable = false
+
+local function read_only(_, key)
+    error("Unable to set " .. tostring(key) .. ", the base environment is read-only", 2)
+end
+
+return function(env)
+    local proxies = {}
+    local function proxy(value)
+        if type(value) ~= "table" then return value end
+        if proxies[value] == nil then
+            proxies[value] = setmetatable({}, {
+                __index = function(_, key) return proxy(value[key]) end,
+                __newindex = read_only,
+                __metatable = false,
+            })
+        end
+        return proxies[value]
+    end
+    return setmetatable(env, { __index = proxy(base), __metatable = false })
+end
+"#;
+
+/// Create the read-only environment that every program's environment falls
+/// back to and return the function that makes an environment fall back to
+/// it. It has the standard library and the metadata of the file.
+fn build_base_environment<'lua>(
+    ctx: rlua::Context<'lua>,
+    file_metadata: &Metadata,
+) -> Result<rlua::Function<'lua>, Error> {
+    let metadata = ctx.create_table()?;
+    for (k, v) in file_metadata.iter() {
+        metadata.set(*k, build_lua_value(ctx, v)?)?;
+    }
+    Ok(ctx.load(BASE_ENVIRONMENT).call((ctx.globals(), metadata))?)
+}
+
+/// Create a fresh environment for a single run of a program with the
+/// metadata of the record that `range` came from.
+fn build_environment<'lua>(
+    ctx: rlua::Context<'lua>,
+    metadata: &Metadata,
+    range: &IpRange,
+) -> Result<rlua::Table<'lua>, Error> {
+    let env = ctx.create_table()?;
+    for (k, v) in metadata.iter() {
+        env.set(*k, build_lua_value(ctx, v)?)?;
+    }
+    env.set("is_ipv4", !range.is_ipv6())?;
+    env.set("is_ipv6", range.is_ipv6())?;
+    let with_base: rlua::Function = ctx.named_registry_value("__with_base_environment")?;
+    Ok(with_base.call(env)?)
+}
+
+/// Run the program stored in the registry under `name`, if there is one,
+/// with a fresh environment and return its result.
+fn run_program(
+    ctx: rlua::Context,
+    name: &str,
+    metadata: &Metadata,
+    range: &IpRange,
+) -> Result<Option<bool>, Error> {
+    match ctx.named_registry_value(name)? {
+        rlua::Value::Function(func) => {
+            let set_environment: rlua::Function = ctx.named_registry_value("__set_environment")?;
+            set_environment.call::<_, ()>(build_environment(ctx, metadata, range)?)?;
+            Ok(Some(func.call(())?))
+        }
+        rlua::Nil => Ok(None),
+        _ => bail!("Unexpected value for {}", name),
+    }
+}
+
+/// Filter and select the ranges with the Lua programs. Each time a program
+/// runs, it gets a fresh environment with the metadata of the range's record,
+/// so neither the metadata of other records nor any globals that the program
+/// sets are visible. Names that aren't in the environment are looked up in a
+/// read-only base environment with the standard library and the metadata of
+/// the file.
 pub fn filter_select(
-    range_group: Vec<RangesWithMetadata>,
+    cloud_ranges: CloudRanges,
     filter_program: Option<&str>,
     select_program: Option<&str>,
-) -> Result<Vec<RangeInterest<IpRange>>, Error> {
+) -> Result<Vec<RangeWithMetadata>, Error> {
+    let CloudRanges {
+        file_metadata,
+        ranges: range_group,
+        ..
+    } = cloud_ranges;
+
     if filter_program.is_none() && select_program.is_none() {
         let out = range_group
             .into_iter()
-            .flat_map(|n| n.ranges.into_iter().map(|n| RangeInterest::new(n, true)))
+            .flat_map(|n| {
+                let metadata = Rc::new(n.metadata);
+                n.ranges.into_iter().map(move |range| RangeWithMetadata {
+                    range: RangeInterest::new(range, true),
+                    metadata: metadata.clone(),
+                })
+            })
             .collect();
         return Ok(out);
     }
 
     let lua = rlua::Lua::new();
     lua.context(|ctx| -> Result<(), Error> {
+        let with_base = build_base_environment(ctx, &file_metadata)?;
+        ctx.set_named_registry_value("__with_base_environment", with_base)?;
+
+        let (proxy, set_environment): (rlua::Table, rlua::Function) =
+            ctx.load(ENVIRONMENT_PROXY).eval()?;
+        ctx.set_named_registry_value("__set_environment", set_environment)?;
         if let Some(filter_program) = filter_program {
-            let func = ctx.load(filter_program).into_function()?;
-            ctx.globals().set("__filter_func", func)?;
+            let func = ctx
+                .load(filter_program)
+                .set_environment(proxy.clone())?
+                .into_function()?;
+            ctx.set_named_registry_value("__filter_func", func)?;
         }
         if let Some(select_program) = select_program {
-            let func = ctx.load(select_program).into_function()?;
-            ctx.globals().set("__select_func", func)?;
+            let func = ctx
+                .load(select_program)
+                .set_environment(proxy)?
+                .into_function()?;
+            ctx.set_named_registry_value("__select_func", func)?;
         }
         Ok(())
     })?;
 
-    let mut output_ranges: Vec<RangeInterest<IpRange>> = Vec::new();
+    let mut output_ranges: Vec<RangeWithMetadata> = Vec::new();
     for ranges_with_metadata in range_group {
-        let metadata = ranges_with_metadata.metadata;
-        lua.context(|ctx| -> Result<(), Error> {
-            for (k, v) in metadata {
-                ctx.globals().set(k, build_lua_value(ctx, v)?)?;
-            }
-            Ok(())
-        })?;
-
+        let metadata = Rc::new(ranges_with_metadata.metadata);
         for range in ranges_with_metadata.ranges {
             let selected = lua.context(|ctx| -> Result<Option<bool>, Error> {
-                ctx.globals().set("is_ipv4", !range.is_ipv6())?;
-                ctx.globals().set("is_ipv6", range.is_ipv6())?;
-
-                match ctx.globals().get("__filter_func")? {
-                    rlua::Value::Function(filter_func) => {
-                        if !filter_func.call(())? {
-                            return Ok(None);
-                        }
-                    }
-                    rlua::Nil => {}
-                    _ => bail!("Unexpected value for __filter_func"),
-                }
-
-                match ctx.globals().get("__select_func")? {
-                    rlua::Value::Function(select_func) => {
-                        let selected = select_func.call(())?;
-                        Ok(Some(selected))
-                    }
-                    rlua::Nil => Ok(Some(true)),
-                    _ => bail!("Unexpected value for __select_func"),
+                if run_program(ctx, "__filter_func", &metadata, &range)? == Some(false) {
+                    return Ok(None);
                 }
+                Ok(Some(
+                    run_program(ctx, "__select_func", &metadata, &range)?.unwrap_or(true),
+                ))
             })?;
             if let Some(selected) = selected {
-                output_ranges.push(RangeInterest::new(range, selected))
+                output_ranges.push(RangeWithMetadata {
+                    range: RangeInterest::new(range, selected),
+                    metadata: metadata.clone(),
+                })
             }
         }
     }
 
     Ok(output_ranges)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{filter_select, CloudRanges, Metadata, RangesWithMetadata};
+    use libnetrangemerge::IpRange;
+    use std::str::FromStr;
+
+    fn record(metadata: &[(&'static str, &str)], range: &str) -> RangesWithMetadata {
+        let metadata: Metadata = metadata
+            .iter()
+            .map(|(k, v)| (*k, v.to_string().into()))
+            .collect();
+        RangesWithMetadata::new(metadata, vec![IpRange::from_str(range).unwrap()])
+    }
+
+    fn ranges() -> CloudRanges {
+        let mut ranges = CloudRanges::new(vec![
+            record(
+                &[("service", "EC2"), ("region", "us-east-1")],
+                "10.0.0.0/24",
+            ),
+            record(&[("service", "S3")], "10.0.1.0/24"),
+            record(&[("service", "EC2")], "2001:db8::/32"),
+        ]);
+        ranges
+            .file_metadata
+            .insert("syncToken", "1".to_string().into());
+        ranges
+    }
+
+    fn filtered(filter: &str) -> Vec<String> {
+        filter_select(ranges(), Some(filter), None)
+            .unwrap()
+            .iter()
+            .map(|r| r.range.range().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_missing_keys_are_nil() {
+        assert_eq!(
+            filtered("return region == nil"),
+            vec!["10.0.1.0/24", "2001:db8::/32"]
+        );
+        assert_eq!(filtered("return region ~= nil"), vec!["10.0.0.0/24"]);
+        assert_eq!(filtered("return undefined == nil").len(), 3);
+    }
+
+    #[test]
+    fn test_globals_are_not_shared() {
+        // A global set by one run isn't visible to the next
+        assert_eq!(
+            filtered("local seen = count; count = 1; return seen == nil").len(),
+            3
+        );
+        // Nor is one set by the filter visible to the select program
+        let selected: Vec<bool> = filter_select(
+            ranges(),
+            Some("x = 1; return true"),
+            Some("return x == nil"),
+        )
+        .unwrap()
+        .iter()
+        .map(|r| r.range.is_selected())
+        .collect();
+        assert_eq!(selected, vec![true, true, true]);
+        // Metadata can be assigned to without affecting other records
+        assert_eq!(
+            filtered("local old = service; service = 'changed'; return old == 'EC2'"),
+            vec!["10.0.0.0/24", "2001:db8::/32"]
+        );
+    }
+
+    #[test]
+    fn test_base_environment() {
+        assert_eq!(
+            filtered("return is_ipv6 and string.len(syncToken) == 1"),
+            vec!["2001:db8::/32"]
+        );
+        assert!(filter_select(ranges(), Some("_G.x = 1; return true"), None).is_err());
+        assert!(filter_select(ranges(), Some("syncToken = '2'; return true"), None).is_ok());
+        assert!(filter_select(ranges(), Some("setmetatable(_G, nil)"), None).is_err());
+        assert!(filter_select(ranges(), Some("getmetatable('').__index = {}"), None).is_err());
+    }
+
+    #[test]
+    fn test_base_environment_rawset() {
+        // Raw sets only change the view of the base environment of one run
+        assert_eq!(
+            filtered("local seen = x; rawset(_G, 'x', 1); return seen == nil and x == 1"),
+            vec!["10.0.0.0/24", "10.0.1.0/24", "2001:db8::/32"]
+        );
+        assert_eq!(
+            filtered("local len = string.len; rawset(string, 'len', 1); return len ~= 1"),
+            vec!["10.0.0.0/24", "10.0.1.0/24", "2001:db8::/32"]
+        );
+    }
+
+    #[test]
+    fn test_base_environment_libraries() {
+        assert!(filter_select(ranges(), Some("string.len = nil"), None).is_err());
+        assert!(filter_select(ranges(), Some("table.insert = nil"), None).is_err());
+        assert!(filter_select(ranges(), Some("package.loaded.string.len = nil"), None).is_err());
+        // Changes to the real libraries don't reach the base environment
+        assert_eq!(
+            filtered("load('string.len = nil')(); return string.len('ab') == ('ab'):len()"),
+            vec!["10.0.0.0/24", "10.0.1.0/24", "2001:db8::/32"]
+        );
+    }
+}
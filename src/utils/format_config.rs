@@ -0,0 +1,214 @@
+use crate::formats::{
+    apache, bird, cisco, cloudformation, csv, envoy, haproxy, json, juniper, kubernetes,
+    lookup_table, mmdb, nginx, plain, terraform, OutputContext, OutputRange,
+};
+use crate::OutputOptions;
+use anyhow::{bail, Error};
+use once_cell::sync::Lazy;
+use std::io::Write;
+use std::ops::Deref;
+
+pub type WriteRangesFunc =
+    fn(&mut dyn Write, &[OutputRange], &OutputOptions, &OutputContext) -> Result<(), Error>;
+
+/// How a format records the metadata of the source range
+/// file with `--source-header`.
+pub enum SourceHeader {
+    /// As comment lines that start with the given prefix.
+    Comment(&'static str),
+    /// By the format's write function itself.
+    Builtin,
+    Unsupported,
+}
+
+pub struct FormatConfig {
+    format_name: &'static str,
+    pub write_ranges_func: WriteRangesFunc,
+    pub file_extension: &'static str,
+    /// `true` if the format isn't text and so can't be embedded into
+    /// the JSON array written for chunked output.
+    pub binary: bool,
+    /// `true` if the format writes a single JSON value, which is embedded
+    /// as-is into the JSON array written for chunked output instead of
+    /// as a string.
+    pub json: bool,
+    pub source_header: SourceHeader,
+}
+
+const CONFIG: &[FormatConfig] = &[
+    FormatConfig {
+        format_name: "plain",
+        write_ranges_func: plain::write_ranges,
+        file_extension: "txt",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "apache",
+        write_ranges_func: apache::write_ranges,
+        file_extension: "conf",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "bird",
+        write_ranges_func: bird::write_ranges,
+        file_extension: "conf",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "cisco-ios",
+        write_ranges_func: cisco::write_ios_ranges,
+        file_extension: "txt",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("!"),
+    },
+    FormatConfig {
+        format_name: "cisco-iosxr",
+        write_ranges_func: cisco::write_iosxr_ranges,
+        file_extension: "txt",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("!"),
+    },
+    FormatConfig {
+        format_name: "cloudformation",
+        write_ranges_func: cloudformation::write_ranges,
+        file_extension: "json",
+        binary: false,
+        json: true,
+        source_header: SourceHeader::Unsupported,
+    },
+    FormatConfig {
+        format_name: "csv",
+        write_ranges_func: csv::write_ranges,
+        file_extension: "csv",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Unsupported,
+    },
+    FormatConfig {
+        format_name: "envoy",
+        write_ranges_func: envoy::write_ranges,
+        file_extension: "yaml",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "haproxy",
+        write_ranges_func: haproxy::write_ranges,
+        file_extension: "acl",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "frr",
+        write_ranges_func: cisco::write_ios_ranges,
+        file_extension: "conf",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("!"),
+    },
+    FormatConfig {
+        format_name: "json",
+        write_ranges_func: json::write_ranges,
+        file_extension: "json",
+        binary: false,
+        json: true,
+        source_header: SourceHeader::Builtin,
+    },
+    FormatConfig {
+        format_name: "juniper",
+        write_ranges_func: juniper::write_ranges,
+        file_extension: "txt",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "kubernetes",
+        write_ranges_func: kubernetes::write_ranges,
+        file_extension: "yaml",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "lookup-table",
+        write_ranges_func: lookup_table::write_ranges,
+        file_extension: "bin",
+        binary: true,
+        json: false,
+        source_header: SourceHeader::Unsupported,
+    },
+    FormatConfig {
+        format_name: "mmdb",
+        write_ranges_func: mmdb::write_ranges,
+        file_extension: "mmdb",
+        binary: true,
+        json: false,
+        source_header: SourceHeader::Unsupported,
+    },
+    FormatConfig {
+        format_name: "ndjson",
+        write_ranges_func: json::write_ndjson_ranges,
+        file_extension: "ndjson",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Unsupported,
+    },
+    FormatConfig {
+        format_name: "nginx",
+        write_ranges_func: nginx::write_ranges,
+        file_extension: "conf",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "nginx-geo",
+        write_ranges_func: nginx::write_geo_ranges,
+        file_extension: "conf",
+        binary: false,
+        json: false,
+        source_header: SourceHeader::Comment("#"),
+    },
+    FormatConfig {
+        format_name: "terraform",
+        write_ranges_func: terraform::write_variable_ranges,
+        file_extension: "tf.json",
+        binary: false,
+        json: true,
+        source_header: SourceHeader::Unsupported,
+    },
+    FormatConfig {
+        format_name: "terraform-locals",
+        write_ranges_func: terraform::write_locals_ranges,
+        file_extension: "tf.json",
+        binary: false,
+        json: true,
+        source_header: SourceHeader::Unsupported,
+    },
+];
+
+pub fn get_format_names() -> &'static Vec<&'static str> {
+    static INSTANCE: Lazy<Vec<&'static str>> =
+        Lazy::new(|| CONFIG.iter().map(|fc| fc.format_name).collect());
+    INSTANCE.deref()
+}
+
+pub fn get_format_config(format: &str) -> Result<&'static FormatConfig, Error> {
+    let fc = CONFIG.iter().find(|fc| fc.format_name == format);
+    if let Some(fc) = fc {
+        Ok(fc)
+    } else {
+        bail!("Invalid output format: {}", format)
+    }
+}
@@ -1,25 +1,55 @@
 use crate::utils::cloud_config::get_cloud_config;
-use crate::utils::filter_select::RangesWithMetadata;
-use anyhow::Error;
+use crate::utils::fetch::fetch_cloud_ranges;
+use crate::utils::filter_select::CloudRanges;
+use crate::utils::timestamp::format_timestamp;
+use crate::CacheOptions;
+use anyhow::{anyhow, bail, Error};
 use libnetrangemerge::{IpRange, RangeInterest};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Download ranges from the internet for the named
-/// service and then load them into a Vec<RangesWithMetadata>
-/// suitable for filtering and selecting.
-pub fn fetch_and_load_cloud_ranges(service: &str) -> Result<Vec<RangesWithMetadata>, Error> {
-    let cc = get_cloud_config(service)?;
-    let fetch_func = cc.fetch_ranges_func;
-    let load_func = cc.load_ranges_func;
-    let ranges = load_func(&mut fetch_func()?)?;
-    Ok(ranges)
+/// Download ranges from the internet, or get them from the cache, for
+/// the named service and then load them into a CloudRanges suitable
+/// for filtering and selecting. Every record has a "provider" attribute
+/// naming the service.
+pub fn fetch_and_load_cloud_ranges(
+    service: &str,
+    cache_options: &CacheOptions,
+) -> Result<CloudRanges, Error> {
+    let load_func = get_cloud_config(service)?.load_ranges_func;
+    let body = fetch_cloud_ranges(service, cache_options)?;
+    let ranges = load_func(&mut body.as_slice())?;
+    Ok(ranges.with_provider(service))
+}
+
+/// Download ranges, or get them from the cache, for each of the named
+/// services, check that each file is no older than `max_source_age`, and
+/// combine them into a single CloudRanges.
+pub fn fetch_and_load_services_ranges(
+    services: &[String],
+    cache_options: &CacheOptions,
+    max_source_age: Option<u64>,
+) -> Result<CloudRanges, Error> {
+    let mut loaded: Vec<&String> = Vec::new();
+    let mut combined = Vec::new();
+    for service in services {
+        if loaded.contains(&service) {
+            continue;
+        }
+        let ranges = fetch_and_load_cloud_ranges(service, cache_options)?;
+        check_source_age(service, &ranges, max_source_age)?;
+        loaded.push(service);
+        combined.push(ranges);
+    }
+    Ok(CloudRanges::combine(combined))
 }
 
-/// Load ranges from a file for the named service into a Vec<RangesWithMetadata>
-/// suitable for filtering and selecting.
-pub fn load_cloud_ranges(service: &str, file: PathBuf) -> Result<Vec<RangesWithMetadata>, Error> {
+/// Load ranges from a file for the named service into a CloudRanges
+/// suitable for filtering and selecting. Every record has a "provider"
+/// attribute naming the service.
+pub fn load_cloud_ranges(service: &str, file: PathBuf) -> Result<CloudRanges, Error> {
     let stdin = io::stdin();
     let cc = get_cloud_config(service)?;
     let load_func = cc.load_ranges_func;
@@ -28,12 +58,76 @@ pub fn load_cloud_ranges(service: &str, file: PathBuf) -> Result<Vec<RangesWithM
     } else {
         load_func(&mut File::open(&file)?)?
     };
-    Ok(ranges)
+    Ok(ranges.with_provider(service))
+}
+
+/// Load ranges from each of the files of the named services, check that each
+/// file is no older than `max_source_age`, and combine them into a single
+/// CloudRanges.
+pub fn load_services_ranges(
+    files: Vec<(String, PathBuf)>,
+    max_source_age: Option<u64>,
+) -> Result<CloudRanges, Error> {
+    let mut combined = Vec::new();
+    for (service, file) in files {
+        let ranges = load_cloud_ranges(&service, file)?;
+        check_source_age(&service, &ranges, max_source_age)?;
+        combined.push(ranges);
+    }
+    Ok(CloudRanges::combine(combined))
+}
+
+/// Parse a "<service>=<path>" value of a "--file" option.
+pub fn parse_service_file(value: &str) -> Result<(String, PathBuf), Error> {
+    let mut parts = value.splitn(2, '=');
+    let service = parts.next().unwrap();
+    let path = parts
+        .next()
+        .ok_or_else(|| anyhow!("Invalid --file value, expected <service>=<path>: {}", value))?;
+    get_cloud_config(service)?;
+    Ok((service.to_string(), PathBuf::from(path)))
+}
+
+/// Fail if `max_age` is given and the service created its range file
+/// more than `max_age` seconds ago.
+pub fn check_source_age(
+    service: &str,
+    ranges: &CloudRanges,
+    max_age: Option<u64>,
+) -> Result<(), Error> {
+    let max_age = match max_age {
+        Some(max_age) => max_age,
+        None => return Ok(()),
+    };
+    let created_at = match ranges.created_at {
+        Some(created_at) => created_at,
+        None => bail!(
+            "The {} range file doesn't say when it was created in a form that can be read, \
+             so its age can't be checked",
+            service
+        ),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let age = now.saturating_sub(created_at);
+    if age > max_age {
+        bail!(
+            "The {} range file was created at {}, which is {} seconds ago and more than the maximum of {}",
+            service,
+            format_timestamp(created_at),
+            age,
+            max_age
+        );
+    }
+    Ok(())
 }
 
 /// Load a list of ranges from the `reader`, expecting a single
-/// CIDR range per-line. Loaded ranged are pushed on to the end of
-/// `ranges`.
+/// CIDR range per-line. Blank lines and comment lines starting with "#",
+/// such as those written by `--source-header`, are skipped. Loaded ranged
+/// are pushed on to the end of `ranges`.
 pub fn read_single_line_ranges(
     reader: &mut dyn io::Read,
     ranges: &mut Vec<RangeInterest<IpRange>>,
@@ -42,6 +136,10 @@ pub fn read_single_line_ranges(
     let bufreader = io::BufReader::new(reader);
     for line in bufreader.lines() {
         let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
         let range: IpRange = line.parse()?;
         ranges.push(RangeInterest::new(range, interesting));
     }
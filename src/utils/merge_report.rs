@@ -0,0 +1,205 @@
+use crate::formats::OutputRange;
+use crate::utils::address_count::{count_union, range_bounds, AddressCount};
+use crate::utils::table::format_table;
+use crate::ReportOptions;
+use anyhow::{bail, Error};
+use libnetrangemerge::{IpRange, RangeInterest};
+
+#[derive(Debug, Default)]
+pub struct FamilyReport {
+    pub input_ranges: usize,
+    pub output_ranges: usize,
+    pub selected_addresses: AddressCount,
+    pub output_addresses: AddressCount,
+}
+
+impl FamilyReport {
+    /// The number of output addresses that aren't covered by
+    /// any of the selected input ranges.
+    pub fn overshoot(&self) -> AddressCount {
+        self.output_addresses
+            .saturating_sub(self.selected_addresses)
+    }
+}
+
+/// A summary of how much address space a merge covers compared to
+/// the selected ranges that went into it.
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    pub ipv4: FamilyReport,
+    pub ipv6: FamilyReport,
+}
+
+impl MergeReport {
+    /// Build a report from the ranges that were input to the merge, before
+    /// they were expanded to any minimum network size, and the ranges that
+    /// are output.
+    pub fn new(input: &[RangeInterest<IpRange>], output: &[OutputRange]) -> MergeReport {
+        let mut report = MergeReport::default();
+        let mut selected_ipv4 = Vec::new();
+        let mut selected_ipv6 = Vec::new();
+        for range in input {
+            let (is_ipv6, first, last) = range_bounds(range.range());
+            if is_ipv6 {
+                report.ipv6.input_ranges += 1;
+            } else {
+                report.ipv4.input_ranges += 1;
+            }
+            if range.is_selected() {
+                if is_ipv6 {
+                    selected_ipv6.push((first, last));
+                } else {
+                    selected_ipv4.push((first, last));
+                }
+            }
+        }
+        report.ipv4.selected_addresses = count_union(selected_ipv4);
+        report.ipv6.selected_addresses = count_union(selected_ipv6);
+
+        let mut output_ipv4 = Vec::new();
+        let mut output_ipv6 = Vec::new();
+        for range in output {
+            let (is_ipv6, first, last) = range_bounds(&range.range);
+            if is_ipv6 {
+                report.ipv6.output_ranges += 1;
+                output_ipv6.push((first, last));
+            } else {
+                report.ipv4.output_ranges += 1;
+                output_ipv4.push((first, last));
+            }
+        }
+        report.ipv4.output_addresses = count_union(output_ipv4);
+        report.ipv6.output_addresses = count_union(output_ipv6);
+
+        report
+    }
+
+    /// Print the report to STDERR, under a heading if one is given.
+    pub fn print(&self, heading: Option<&str>) {
+        let headers = [
+            "family",
+            "input ranges",
+            "output ranges",
+            "selected addresses",
+            "output addresses",
+            "overshoot",
+        ];
+        let rows: Vec<Vec<String>> = [("ipv4", &self.ipv4), ("ipv6", &self.ipv6)]
+            .iter()
+            .map(|(family, report)| {
+                vec![
+                    family.to_string(),
+                    report.input_ranges.to_string(),
+                    report.output_ranges.to_string(),
+                    report.selected_addresses.to_string(),
+                    report.output_addresses.to_string(),
+                    report.overshoot().to_string(),
+                ]
+            })
+            .collect();
+
+        if let Some(heading) = heading {
+            eprintln!("{}", heading);
+        }
+        eprint!("{}", format_table(&headers, &rows));
+    }
+
+    /// Fail if the overshoot of either address family is larger
+    /// than `max_overshoot`.
+    pub fn check_overshoot(&self, max_overshoot: u128) -> Result<(), Error> {
+        for (family, report) in &[("ipv4", &self.ipv4), ("ipv6", &self.ipv6)] {
+            let overshoot = report.overshoot();
+            if overshoot > AddressCount::from(max_overshoot) {
+                bail!(
+                    "The {} output covers {} addresses that aren't selected, which is more than the maximum of {}",
+                    family,
+                    overshoot,
+                    max_overshoot,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the report and check it against the maximum overshoot, as
+/// requested by `options`.
+pub fn handle_merge_report(
+    options: &ReportOptions,
+    input: &[RangeInterest<IpRange>],
+    output: &[OutputRange],
+    heading: Option<&str>,
+) -> Result<(), Error> {
+    if !options.report && options.max_overshoot.is_none() {
+        return Ok(());
+    }
+    let report = MergeReport::new(input, output);
+    if options.report {
+        report.print(heading);
+    }
+    if let Some(max_overshoot) = options.max_overshoot {
+        report.check_overshoot(max_overshoot)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::OutputRange;
+    use crate::utils::address_count::AddressCount;
+    use crate::utils::merge_report::MergeReport;
+    use libnetrangemerge::RangeInterest;
+
+    fn input(ranges: &[(&str, bool)]) -> Vec<RangeInterest<libnetrangemerge::IpRange>> {
+        ranges
+            .iter()
+            .map(|(r, selected)| RangeInterest::new(r.parse().unwrap(), *selected))
+            .collect()
+    }
+
+    fn output(ranges: &[&str]) -> Vec<OutputRange> {
+        ranges
+            .iter()
+            .map(|r| OutputRange::new(r.parse().unwrap(), true))
+            .collect()
+    }
+
+    #[test]
+    fn test_report() {
+        let report = MergeReport::new(
+            &input(&[
+                ("127.0.0.0/25", true),
+                ("127.0.0.0/26", true),
+                ("127.0.0.128/25", false),
+                ("2600::/127", true),
+            ]),
+            &output(&["127.0.0.0/24", "2600::/127"]),
+        );
+        assert_eq!(report.ipv4.input_ranges, 3);
+        assert_eq!(report.ipv4.output_ranges, 1);
+        assert_eq!(report.ipv4.selected_addresses, AddressCount::from(128));
+        assert_eq!(report.ipv4.output_addresses, AddressCount::from(256));
+        assert_eq!(report.ipv4.overshoot(), AddressCount::from(128));
+        assert_eq!(report.ipv6.overshoot(), AddressCount::from(0));
+        assert!(report.check_overshoot(128).is_ok());
+        assert!(report.check_overshoot(127).is_err());
+    }
+
+    #[test]
+    fn test_full_address_space() {
+        let report = MergeReport::new(
+            &input(&[("::/1", true), ("8000::/1", false), ("0.0.0.0/0", true)]),
+            &output(&["::/0", "0.0.0.0/0"]),
+        );
+        assert_eq!(
+            report.ipv6.output_addresses.to_string(),
+            "340282366920938463463374607431768211456"
+        );
+        assert_eq!(
+            report.ipv6.overshoot().to_string(),
+            "170141183460469231731687303715884105728"
+        );
+        assert_eq!(report.ipv4.output_addresses, AddressCount::from(1 << 32));
+        assert_eq!(report.ipv4.overshoot(), AddressCount::from(0));
+    }
+}
@@ -1,5 +1,17 @@
+pub mod address_count;
+pub mod archive;
+pub mod attributes;
 pub mod cloud_config;
 pub mod cloud_process_ranges;
+pub mod collect_metadata;
+pub mod diff_ranges;
 pub mod expand_ranges;
+pub mod fetch;
 pub mod filter_select;
+pub mod format_config;
 pub mod load_ranges;
+pub mod merge_report;
+pub mod range_stats;
+pub mod table;
+pub mod timestamp;
+pub mod write_ranges;
@@ -0,0 +1,183 @@
+use crate::utils::address_count::{count_union, range_bounds, AddressCount};
+use crate::utils::filter_select::{MetadataValue, RangeWithMetadata};
+use crate::utils::table::format_table;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The ranges of each address family that were counted.
+#[derive(Default)]
+struct Intervals {
+    ipv4: Vec<(u128, u128)>,
+    ipv6: Vec<(u128, u128)>,
+}
+
+impl Intervals {
+    fn add(&mut self, range: &RangeWithMetadata) {
+        let (is_ipv6, first, last) = range_bounds(range.range.range());
+        if is_ipv6 {
+            self.ipv6.push((first, last));
+        } else {
+            self.ipv4.push((first, last));
+        }
+    }
+}
+
+/// Counts of the ranges of a group and the number of distinct
+/// addresses that they cover, by address family.
+pub struct RangeCounts {
+    pub ipv4_ranges: usize,
+    pub ipv6_ranges: usize,
+    pub ipv4_addresses: AddressCount,
+    pub ipv6_addresses: AddressCount,
+}
+
+impl From<Intervals> for RangeCounts {
+    fn from(intervals: Intervals) -> RangeCounts {
+        RangeCounts {
+            ipv4_ranges: intervals.ipv4.len(),
+            ipv6_ranges: intervals.ipv6.len(),
+            ipv4_addresses: count_union(intervals.ipv4),
+            ipv6_addresses: count_union(intervals.ipv6),
+        }
+    }
+}
+
+impl RangeCounts {
+    fn to_row(&self, name: String) -> Vec<String> {
+        vec![
+            name,
+            self.ipv4_ranges.to_string(),
+            self.ipv6_ranges.to_string(),
+            self.ipv4_addresses.to_string(),
+            self.ipv6_addresses.to_string(),
+        ]
+    }
+
+    fn to_json(&self) -> Map<String, Value> {
+        // Address counts are strings since ipv6 counts can be too
+        // large for many JSON parsers to represent exactly.
+        let value = json!({
+            "ipv4_ranges": self.ipv4_ranges,
+            "ipv6_ranges": self.ipv6_ranges,
+            "ipv4_addresses": self.ipv4_addresses.to_string(),
+            "ipv6_addresses": self.ipv6_addresses.to_string(),
+        });
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Counts of ranges and addresses overall and grouped by the
+/// values of metadata attributes.
+pub struct RangeStats {
+    pub total: RangeCounts,
+    /// For each attribute, the counts for each of its values. Ranges that
+    /// don't have the attribute are counted under `None`.
+    pub by_attribute: BTreeMap<String, BTreeMap<Option<MetadataValue>, RangeCounts>>,
+}
+
+impl RangeStats {
+    /// Count `ranges` grouped by each attribute in `attributes`, or by
+    /// every attribute that any of the ranges has if `attributes` is empty.
+    pub fn new(ranges: &[RangeWithMetadata], attributes: &[String]) -> RangeStats {
+        let attributes: BTreeSet<String> = if attributes.is_empty() {
+            ranges
+                .iter()
+                .flat_map(|r| r.metadata.keys().map(|k| k.to_string()))
+                .collect()
+        } else {
+            attributes.iter().cloned().collect()
+        };
+
+        let mut total = Intervals::default();
+        let mut by_attribute: BTreeMap<String, BTreeMap<Option<MetadataValue>, Intervals>> =
+            BTreeMap::new();
+        for range in ranges {
+            total.add(range);
+            for attribute in &attributes {
+                by_attribute
+                    .entry(attribute.clone())
+                    .or_default()
+                    .entry(range.metadata.get(attribute.as_str()).cloned())
+                    .or_default()
+                    .add(range);
+            }
+        }
+
+        RangeStats {
+            total: total.into(),
+            by_attribute: by_attribute
+                .into_iter()
+                .map(|(attribute, values)| {
+                    let values = values
+                        .into_iter()
+                        .map(|(value, intervals)| (value, intervals.into()))
+                        .collect();
+                    (attribute, values)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format_table(
+            &[
+                "total",
+                "ipv4 ranges",
+                "ipv6 ranges",
+                "ipv4 addresses",
+                "ipv6 addresses",
+            ],
+            &[self.total.to_row("all".to_string())],
+        );
+        for (attribute, values) in &self.by_attribute {
+            let rows: Vec<Vec<String>> = values
+                .iter()
+                .map(|(value, counts)| {
+                    let name = match value {
+                        Some(value) => value.to_string(),
+                        None => "(none)".to_string(),
+                    };
+                    counts.to_row(name)
+                })
+                .collect();
+            table.push('\n');
+            table.push_str(&format_table(
+                &[
+                    attribute,
+                    "ipv4 ranges",
+                    "ipv6 ranges",
+                    "ipv4 addresses",
+                    "ipv6 addresses",
+                ],
+                &rows,
+            ));
+        }
+        table
+    }
+
+    pub fn to_json(&self) -> Value {
+        let by_attribute: Map<String, Value> = self
+            .by_attribute
+            .iter()
+            .map(|(attribute, values)| {
+                let values = values
+                    .iter()
+                    .map(|(value, counts)| {
+                        let mut object = Map::new();
+                        object.insert("value".to_string(), json!(value));
+                        object.extend(counts.to_json());
+                        Value::Object(object)
+                    })
+                    .collect();
+                (attribute.clone(), Value::Array(values))
+            })
+            .collect();
+        json!({
+            "total": self.total.to_json(),
+            "by_attribute": by_attribute,
+        })
+    }
+}
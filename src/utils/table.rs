@@ -0,0 +1,34 @@
+/// Format `rows` as a table with a line of `headers` above them. The first
+/// column is aligned to the left and every other column to the right.
+pub fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(idx, header)| {
+            rows.iter()
+                .map(|row| row[idx].len())
+                .chain(std::iter::once(header.len()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+    let format_line = |values: &mut dyn Iterator<Item = &str>| -> String {
+        let columns: Vec<String> = values
+            .enumerate()
+            .map(|(idx, value)| {
+                if idx == 0 {
+                    format!("{:<width$}", value, width = widths[idx])
+                } else {
+                    format!("{:>width$}", value, width = widths[idx])
+                }
+            })
+            .collect();
+        columns.join("  ").trim_end().to_string() + "\n"
+    };
+
+    let mut table = format_line(&mut headers.iter().copied());
+    for row in rows {
+        table.push_str(&format_line(&mut row.iter().map(|v| v.as_str())));
+    }
+    table
+}
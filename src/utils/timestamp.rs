@@ -0,0 +1,142 @@
+use anyhow::{anyhow, bail, Error};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The number of days from 1970-01-01 to the given date in the
+/// proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date that is `days` days after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn parse_number(value: &str, min: i64, max: i64) -> Option<i64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok().filter(|n| *n >= min && *n <= max)
+}
+
+/// Parse a timestamp into seconds since the Unix epoch. The timestamp is
+/// either a number of seconds since the epoch or a UTC date, optionally
+/// followed by a time, such as "2021-03-03", "2021-03-03T12:00:00Z" or
+/// "2021-03-03 12:00".
+pub fn parse_timestamp(value: &str) -> Result<u64, Error> {
+    let value = value.trim();
+    if let Some(seconds) = parse_number(value, 0, i64::MAX) {
+        return Ok(seconds as u64);
+    }
+
+    let invalid = || anyhow!("Invalid timestamp: {}", value);
+    let value_without_zone = value.trim_end_matches('Z');
+    let (date, time) = match value_without_zone.find(&['T', ' '][..]) {
+        Some(i) => (&value_without_zone[..i], Some(&value_without_zone[i + 1..])),
+        None => (value_without_zone, None),
+    };
+
+    let date: Vec<&str> = date.split('-').collect();
+    if date.len() != 3 || date[0].len() != 4 {
+        return Err(invalid());
+    }
+    let year = parse_number(date[0], 1970, 9999).ok_or_else(invalid)?;
+    let month = parse_number(date[1], 1, 12).ok_or_else(invalid)?;
+    let day = parse_number(date[2], 1, 31).ok_or_else(invalid)?;
+
+    let mut seconds_of_day = 0;
+    if let Some(time) = time {
+        let time: Vec<&str> = time.split(':').collect();
+        if time.len() < 2 || time.len() > 3 {
+            return Err(invalid());
+        }
+        let hour = parse_number(time[0], 0, 23).ok_or_else(invalid)?;
+        let minute = parse_number(time[1], 0, 59).ok_or_else(invalid)?;
+        let second = match time.get(2) {
+            // Fractional seconds are accepted, but ignored
+            Some(s) => parse_number(s.split('.').next().unwrap(), 0, 60).ok_or_else(invalid)?,
+            None => 0,
+        };
+        seconds_of_day = hour * 3600 + minute * 60 + second;
+    }
+
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        bail!("Invalid date: {}", value);
+    }
+    Ok((days * SECONDS_PER_DAY + seconds_of_day) as u64)
+}
+
+/// Format seconds since the Unix epoch as a UTC timestamp,
+/// for example: "2021-03-03T12:00:00Z".
+pub fn format_timestamp(timestamp: u64) -> String {
+    let timestamp = timestamp as i64;
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(SECONDS_PER_DAY));
+    let seconds_of_day = timestamp.rem_euclid(SECONDS_PER_DAY);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_timestamp, parse_timestamp};
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("0").unwrap(), 0);
+        assert_eq!(parse_timestamp("1614772800").unwrap(), 1614772800);
+        assert_eq!(parse_timestamp("2021-03-03").unwrap(), 1614729600);
+        assert_eq!(parse_timestamp("2021-03-03T12:00:00Z").unwrap(), 1614772800);
+        assert_eq!(parse_timestamp("2021-03-03 12:00").unwrap(), 1614772800);
+        assert_eq!(
+            parse_timestamp("2021-03-03T12:00:00.25").unwrap(),
+            1614772800
+        );
+        assert_eq!(parse_timestamp("2024-02-29").unwrap(), 1709164800);
+
+        assert!(parse_timestamp("2021-02-29").is_err());
+        assert!(parse_timestamp("2021-13-01").is_err());
+        assert!(parse_timestamp("2021-03-03T25:00").is_err());
+        assert!(parse_timestamp("21-03-03").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1614772800), "2021-03-03T12:00:00Z");
+        assert_eq!(format_timestamp(1709164800), "2024-02-29T00:00:00Z");
+        assert_eq!(
+            format_timestamp(parse_timestamp("2099-12-31T23:59:59Z").unwrap()),
+            "2099-12-31T23:59:59Z"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::formats::{template, OutputContext, OutputRange};
+use crate::utils::format_config::{get_format_config, SourceHeader, WriteRangesFunc};
+use crate::OutputOptions;
+use anyhow::{bail, Error};
+use libnetrangemerge::Range as _;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Write `ranges` to the output file, or STDOUT if there is none, using
+/// the output template or the output format selected in `options`.
+pub fn write_ranges(
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    if let Some(output_file) = &options.output_file {
+        write_ranges_to_file(output_file, ranges, options, context)
+    } else if options.chunk_size.is_some() {
+        let stdout = io::stdout();
+        let mut stdout = BufWriter::new(stdout.lock());
+        write_chunks_json(&mut stdout, ranges, options, context)?;
+        stdout.flush()?;
+        Ok(())
+    } else {
+        let stdout = io::stdout();
+        let mut stdout = BufWriter::new(stdout.lock());
+        write_output(&mut stdout, ranges, options, context)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Write `ranges` to `output_file`, ignoring the output file
+/// set in `options`. If the output is split into chunks, each chunk is
+/// written to a file with the chunk number added to the name.
+pub fn write_ranges_to_file(
+    output_file: &Path,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    if options.chunk_size.is_some() {
+        let extension = output_file_extension(options)?;
+        for (idx, chunk) in chunk_ranges(ranges, options)?.iter().enumerate() {
+            let chunk_context = OutputContext {
+                chunk: Some(idx + 1),
+                ..context.clone()
+            };
+            let chunk_file = chunk_file_name(output_file, extension, idx + 1);
+            let mut out = BufWriter::new(File::create(chunk_file)?);
+            write_output(&mut out, chunk, options, &chunk_context)?;
+            out.flush()?;
+        }
+    } else {
+        let mut out = BufWriter::new(File::create(output_file)?);
+        write_output(&mut out, ranges, options, context)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Get the file extension for files written with the output
+/// format selected in `options`.
+pub fn output_file_extension(options: &OutputOptions) -> Result<&'static str, Error> {
+    if options.output_template.is_some() {
+        Ok("txt")
+    } else {
+        Ok(get_format_config(&options.output_format)?.file_extension)
+    }
+}
+
+fn get_write_ranges_func(options: &OutputOptions) -> Result<WriteRangesFunc, Error> {
+    if options.output_template.is_some() {
+        Ok(template::write_ranges)
+    } else {
+        Ok(get_format_config(&options.output_format)?.write_ranges_func)
+    }
+}
+
+/// Write the source header, if requested, followed by `ranges` using
+/// the output template or the output format selected in `options`.
+fn write_output(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    // Templates can include the "source" value wherever they like
+    if options.source_header && options.output_template.is_none() {
+        match get_format_config(&options.output_format)?.source_header {
+            SourceHeader::Comment(prefix) => write_source_comment(out, prefix, context)?,
+            SourceHeader::Builtin => {}
+            SourceHeader::Unsupported => bail!(
+                "The {} format doesn't support --source-header",
+                options.output_format
+            ),
+        }
+    }
+    get_write_ranges_func(options)?(out, ranges, options, context)
+}
+
+/// Write the service and the metadata of its range file as comment lines.
+fn write_source_comment(
+    out: &mut dyn Write,
+    prefix: &str,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    if let Some(service) = &context.service {
+        writeln!(out, "{} Source: {}", prefix, service)?;
+        for (key, value) in &context.source_metadata {
+            writeln!(out, "{} {}: {}", prefix, key, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Split `ranges` into chunks of at most `--chunk-size` ranges. With
+/// `--chunk-by-family`, all of the ipv4 ranges are chunked first followed
+/// by all of the ipv6 ranges so that no chunk contains both.
+fn chunk_ranges(
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+) -> Result<Vec<Vec<OutputRange>>, Error> {
+    let chunk_size = match options.chunk_size {
+        Some(0) => bail!("The chunk size must be at least 1"),
+        Some(chunk_size) => chunk_size,
+        None => return Ok(vec![ranges.to_vec()]),
+    };
+    let families = if options.chunk_by_family {
+        let (ipv6_ranges, ipv4_ranges): (Vec<_>, Vec<_>) =
+            ranges.iter().cloned().partition(|r| r.range.is_ipv6());
+        vec![ipv4_ranges, ipv6_ranges]
+    } else {
+        vec![ranges.to_vec()]
+    };
+    Ok(families
+        .iter()
+        .flat_map(|family| family.chunks(chunk_size).map(|chunk| chunk.to_vec()))
+        .collect())
+}
+
+/// Add the chunk number to the name of `output_file`, before the extension
+/// of the output format if the file name ends with it.
+fn chunk_file_name(output_file: &Path, extension: &str, chunk: usize) -> PathBuf {
+    let file_name = output_file
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let suffix = format!(".{}", extension);
+    let chunk_file_name = if file_name.len() > suffix.len() && file_name.ends_with(&suffix) {
+        format!(
+            "{}-{}{}",
+            &file_name[..file_name.len() - suffix.len()],
+            chunk,
+            suffix
+        )
+    } else {
+        format!("{}-{}", file_name, chunk)
+    };
+    output_file.with_file_name(chunk_file_name)
+}
+
+/// Write each chunk as an element of a JSON array. Chunks in the "plain" format
+/// are written as an array of ranges, chunks in formats that produce JSON are
+/// embedded as-is, and chunks in any other format are written as a string.
+fn write_chunks_json(
+    out: &mut dyn Write,
+    ranges: &[OutputRange],
+    options: &OutputOptions,
+    context: &OutputContext,
+) -> Result<(), Error> {
+    let (is_plain, is_json) = if options.output_template.is_none() {
+        let format_config = get_format_config(&options.output_format)?;
+        if format_config.binary {
+            bail!(
+                "Chunks of the {} format must be written to files with --output",
+                options.output_format
+            );
+        }
+        (options.output_format == "plain", format_config.json)
+    } else {
+        (false, false)
+    };
+
+    let mut values = Vec::new();
+    for (idx, chunk) in chunk_ranges(ranges, options)?.iter().enumerate() {
+        if is_plain {
+            values.push(Value::Array(
+                chunk
+                    .iter()
+                    .map(|r| Value::String(r.range.to_string()))
+                    .collect(),
+            ));
+            continue;
+        }
+        let chunk_context = OutputContext {
+            chunk: Some(idx + 1),
+            ..context.clone()
+        };
+        let mut data = Vec::new();
+        write_output(&mut data, chunk, options, &chunk_context)?;
+        let text = String::from_utf8(data)?;
+        if is_json {
+            values.push(serde_json::from_str(&text)?);
+        } else {
+            values.push(Value::String(text));
+        }
+    }
+
+    serde_json::to_writer_pretty(&mut *out, &values)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::formats::test_util::{options, output_ranges};
+    use crate::formats::OutputContext;
+    use crate::utils::write_ranges::write_chunks_json;
+    use serde_json::{json, Value};
+
+    fn chunks_json(args: &[&str]) -> Value {
+        let ranges = output_ranges(&["10.0.0.0/24", "10.0.2.0/24", "2001:db8::/32"]);
+        let args = [&["--chunk-size", "2", "--name", "cloud"], args].concat();
+        let mut out = Vec::new();
+        write_chunks_json(
+            &mut out,
+            &ranges,
+            &options(&args),
+            &OutputContext::default(),
+        )
+        .unwrap();
+        serde_json::from_slice(&out).unwrap()
+    }
+
+    #[test]
+    fn test_write_chunks_json() {
+        assert_eq!(
+            chunks_json(&[]),
+            json!([["10.0.0.0/24", "10.0.2.0/24"], ["2001:db8::/32"]])
+        );
+        assert_eq!(
+            chunks_json(&["--output-format", "terraform-locals"]),
+            json!([
+                {"//": "IP ranges", "locals": {"cloud-1": ["10.0.0.0/24", "10.0.2.0/24"]}},
+                {"//": "IP ranges", "locals": {"cloud-2": ["2001:db8::/32"]}},
+            ])
+        );
+        assert_eq!(
+            chunks_json(&["--output-format", "cisco-ios"]),
+            json!([
+                "ip prefix-list cloud-1 seq 5 permit 10.0.0.0/24\n\
+                 ip prefix-list cloud-1 seq 10 permit 10.0.2.0/24\n",
+                "ipv6 prefix-list cloud-2 seq 5 permit 2001:db8::/32\n",
+            ])
+        );
+        assert_eq!(
+            chunks_json(&["--output-format", "nginx-geo"])[1],
+            json!("geo $cloud_2 {\n    default 0;\n    2001:db8::/32 1;\n}\n")
+        );
+    }
+}
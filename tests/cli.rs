@@ -0,0 +1,403 @@
+//! Tests of the command line interface itself.
+
+use std::fs;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+const AWS_RANGES: &str = r#"{
+  "syncToken": "1614772800",
+  "createDate": "2021-03-03-12-00-00",
+  "prefixes": [
+    {"ip_prefix": "10.0.0.0/24", "region": "us-east-1", "service": "EC2", "network_border_group": "us-east-1"},
+    {"ip_prefix": "10.0.1.0/24", "region": "us-east-1", "service": "EC2", "network_border_group": "us-east-1"},
+    {"ip_prefix": "10.1.0.0/24", "region": "eu-west-1", "service": "S3", "network_border_group": "eu-west-1"}
+  ],
+  "ipv6_prefixes": [
+    {"ipv6_prefix": "2001:db8::/32", "region": "eu-west-1", "service": "EC2", "network_border_group": "eu-west-1"}
+  ]
+}"#;
+
+/// Write `contents` to a file named `name` in `dir`, returning its path
+/// as a string to pass on the command line.
+fn write_file(dir: &TempDir, name: &str, contents: &str) -> String {
+    let path = dir.path().join(name);
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn netrange(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_netrange"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "netrange failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn help(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_netrange"))
+        .args(args)
+        .arg("--help")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_subcommand_descriptions() {
+    let cloud_help = help(&["cloud"]);
+    assert!(cloud_help.contains("Download the source file that contains the IP ranges"));
+    assert!(cloud_help.contains("Load IP ranges for the service, merge adjacent ranges"));
+    assert!(!cloud_help.contains("Options "));
+
+    let help = help(&[]);
+    assert!(help.contains("Merge IP ranges to try to minimize the number of ranges"));
+    assert!(!help.contains("Options "));
+}
+
+#[test]
+fn test_group_by() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "aws.json", AWS_RANGES);
+    let output_dir = dir.path().join("out");
+    let output_dir = output_dir.to_str().unwrap();
+
+    let args = &["cloud", "merge", "aws", &file, "--group-by", "region"];
+    let output = netrange(&[&args[..], &["--output-dir", output_dir]].concat());
+    stdout(&output);
+    let read = |name: &str| fs::read_to_string(dir.path().join("out").join(name)).unwrap();
+    assert_eq!(read("us-east-1.txt"), "10.0.0.0/23\n");
+    assert_eq!(read("eu-west-1.txt"), "10.1.0.0/24\n2001:db8::/32\n");
+
+    let output = netrange(&[&args[..], &["--output-dir", output_dir, "--output", "x"]].concat());
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_stats() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "aws.json", AWS_RANGES);
+
+    let output = netrange(&["cloud", "stats", "aws", &file, "--by", "region"]);
+    assert_eq!(
+        stdout(&output),
+        "\
+total  ipv4 ranges  ipv6 ranges  ipv4 addresses                 ipv6 addresses
+all              3            1             768  79228162514264337593543950336
+
+region     ipv4 ranges  ipv6 ranges  ipv4 addresses                 ipv6 addresses
+eu-west-1            1            1             256  79228162514264337593543950336
+us-east-1            2            0             512                              0
+"
+    );
+
+    let output = netrange(&[
+        "cloud", "stats", "aws", &file, "--by", "service", "--format", "json",
+    ]);
+    let stats: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(stats["total"]["ipv4_addresses"], "768");
+    assert_eq!(stats["by_attribute"]["service"][1]["value"], "S3");
+    assert_eq!(stats["by_attribute"]["service"][1]["ipv4_ranges"], 1);
+}
+
+#[test]
+fn test_merge_service_files() {
+    let dir = TempDir::new().unwrap();
+    let aws = format!("aws={}", write_file(&dir, "aws.json", AWS_RANGES));
+    let github = format!(
+        "github={}",
+        write_file(
+            &dir,
+            "github.json",
+            r#"{"hooks": ["192.30.252.0/22"], "web": ["10.0.1.0/24"]}"#
+        )
+    );
+
+    let output = netrange(&[
+        "cloud",
+        "merge",
+        "--file",
+        &aws,
+        "--file",
+        &github,
+        "--filter",
+        "return provider == 'github' or service == 'S3'",
+        "--source-header",
+    ]);
+    assert_eq!(
+        stdout(&output),
+        "\
+# Source: aws,github
+10.0.1.0/24
+10.1.0.0/24
+192.30.252.0/22
+"
+    );
+
+    let output = netrange(&["cloud", "read", "aws", &aws[4..], "--file", &github]);
+    assert_eq!(
+        stdout(&output),
+        "\
+10.0.0.0/24
+10.0.1.0/24
+10.1.0.0/24
+2001:db8::/32
+192.30.252.0/22
+10.0.1.0/24
+"
+    );
+
+    let output = netrange(&["cloud", "read", "aws", "--file", &github]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_attributes() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "aws.json", AWS_RANGES);
+
+    let output = netrange(&["cloud", "attributes", "aws", &file]);
+    assert_eq!(
+        stdout(&output),
+        "\
+network_border_group (string)
+    eu-west-1  2
+    us-east-1  2
+provider (string)
+    aws  4
+region (string)
+    eu-west-1  2
+    us-east-1  2
+service (string)
+    EC2  3
+    S3   1
+"
+    );
+
+    let output = netrange(&["cloud", "attributes", "aws", &file, "--format", "json"]);
+    let attributes: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(
+        attributes["service"],
+        serde_json::json!({
+            "types": ["string"],
+            "values": [{"value": "EC2", "count": 3}, {"value": "S3", "count": 1}],
+        })
+    );
+}
+
+#[test]
+fn test_lookup() {
+    let dir = TempDir::new().unwrap();
+    let file = format!("aws={}", write_file(&dir, "aws.json", AWS_RANGES));
+
+    let output = netrange(&[
+        "lookup",
+        "10.0.0.5",
+        "10.1.0.0/16",
+        "192.168.0.1",
+        "--file",
+        &file,
+    ]);
+    assert_eq!(
+        stdout(&output),
+        "\
+10.0.0.5
+    aws 10.0.0.0/24 (most specific) network_border_group=us-east-1 provider=aws region=us-east-1 service=EC2
+10.1.0.0/16
+    aws 10.1.0.0/24 (within query) network_border_group=eu-west-1 provider=aws region=eu-west-1 service=S3
+192.168.0.1
+    no matches
+"
+    );
+
+    let output = netrange(&["lookup", "2001:db8::1", "--file", &file, "--format", "json"]);
+    let matches: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(matches[0]["query"], "2001:db8::1");
+    assert_eq!(matches[0]["matches"][0]["range"], "2001:db8::/32");
+    assert_eq!(matches[0]["matches"][0]["most_specific"], true);
+
+    // Services given with --file are looked in as well as those given with --service
+    let github = format!(
+        "github={}",
+        write_file(&dir, "github.json", r#"{"hooks": ["10.0.0.0/16"]}"#)
+    );
+    let output = netrange(&[
+        "lookup",
+        "10.0.0.5",
+        "--service",
+        "aws",
+        "--file",
+        &github,
+        "--file",
+        &file,
+        "--format",
+        "json",
+    ]);
+    let matches: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(matches[0]["matches"][0]["service"], "aws");
+    assert_eq!(matches[0]["matches"][1]["service"], "github");
+
+    let output = netrange(&["lookup", "10.0.0.5", "--file", "aws=/nonexistent/aws.json"]);
+    assert!(!output.status.success());
+
+    let output = netrange(&["lookup", "not-an-address", "--file", &file]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_check_exit_codes() {
+    let dir = TempDir::new().unwrap();
+    let ranges_file = write_file(&dir, "ranges.txt", "10.0.0.0/23\n");
+    let service_file = write_file(&dir, "aws.json", AWS_RANGES);
+
+    let output = netrange(&[
+        "check",
+        "10.0.0.5",
+        "10.0.1.0/24",
+        "--ranges-file",
+        &ranges_file,
+    ]);
+    assert_eq!(stdout(&output), "10.0.0.5 covered\n10.0.1.0/24 covered\n");
+
+    let output = netrange(&[
+        "check",
+        "10.0.0.5",
+        "10.0.0.0/22",
+        "192.168.0.1",
+        "--ranges-file",
+        &ranges_file,
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "\
+10.0.0.5 covered
+10.0.0.0/22 partially-covered (512 of 1024 addresses)
+192.168.0.1 not-covered
+"
+    );
+
+    let args = &[
+        "check",
+        "10.0.0.5",
+        "--service",
+        "aws",
+        "--service-file",
+        &service_file,
+    ];
+    assert_eq!(netrange(args).status.code(), Some(0));
+    let select = &["--select", "return region == 'eu-west-1'"];
+    assert_eq!(
+        netrange(&[&args[..], select].concat()).status.code(),
+        Some(2)
+    );
+
+    let output = netrange(&["check", "not-an-address", "--ranges-file", &ranges_file]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_diff_exit_codes() {
+    let dir = TempDir::new().unwrap();
+    let old_file = write_file(&dir, "old.json", AWS_RANGES);
+    // Only an attribute changes, which doesn't change the merged output
+    let changed = AWS_RANGES.replacen(r#""service": "EC2""#, r#""service": "S3""#, 1);
+    let changed_file = write_file(&dir, "changed.json", &changed);
+    let moved = AWS_RANGES.replace("10.1.0.0/24", "10.2.0.0/24");
+    let moved_file = write_file(&dir, "moved.json", &moved);
+
+    let output = netrange(&["cloud", "diff", "aws", &old_file, &changed_file]);
+    let diff = stdout(&output);
+    assert!(diff.starts_with("changed  10.0.0.0/24"), "{}", diff);
+    assert!(diff.contains("ipv4 addresses: 768 -> 768 (+0)\n"));
+
+    let output = netrange(&["cloud", "diff", "aws", &old_file, &moved_file]);
+    assert_eq!(output.status.code(), Some(2));
+    let diff = String::from_utf8(output.stdout).unwrap();
+    assert!(diff.contains("added    10.2.0.0/24 "));
+    assert!(diff.contains("removed  10.1.0.0/24 "));
+    assert!(diff.contains("output added    10.2.0.0/24\noutput removed  10.1.0.0/24\n"));
+
+    let filter = &["--filter", "return region == 'us-east-1'"];
+    let args = &["cloud", "diff", "aws", &old_file, &moved_file];
+    assert_eq!(
+        netrange(&[&args[..], filter].concat()).status.code(),
+        Some(0)
+    );
+
+    let output = netrange(&["cloud", "diff", "aws", &old_file, "missing.json"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_source_metadata() {
+    let dir = TempDir::new().unwrap();
+    let file = write_file(&dir, "aws.json", AWS_RANGES);
+    let undated = AWS_RANGES.replace("2021-03-03-12-00-00", "yesterday");
+    let undated_file = write_file(&dir, "undated.json", &undated);
+
+    // The metadata of the file is available to filter programs as globals
+    let filter = "return syncToken == '1614772800' and is_ipv6";
+    let output = netrange(&["cloud", "read", "aws", &file, "--filter", filter]);
+    assert_eq!(stdout(&output), "2001:db8::/32\n");
+    let filter = "return createDate == 'yesterday' and is_ipv6";
+    let output = netrange(&["cloud", "read", "aws", &undated_file, "--filter", filter]);
+    assert_eq!(stdout(&output), "2001:db8::/32\n");
+
+    let args = &[
+        "cloud",
+        "read",
+        "aws",
+        &file,
+        "--filter",
+        "return is_ipv6",
+        "--source-header",
+    ];
+    assert_eq!(
+        stdout(&netrange(args)),
+        "\
+# Source: aws
+# createDate: 2021-03-03-12-00-00
+# syncToken: 1614772800
+2001:db8::/32
+"
+    );
+    let output = netrange(&[&args[..], &["--output-format", "cisco-ios"]].concat());
+    assert!(stdout(&output).starts_with("! Source: aws\n! createDate: 2021-03-03-12-00-00\n"));
+    let output = netrange(&[&args[..], &["--output-format", "json"]].concat());
+    let doc: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(
+        doc["source"],
+        serde_json::json!({
+            "service": "aws",
+            "metadata": {"createDate": "2021-03-03-12-00-00", "syncToken": "1614772800"},
+        })
+    );
+    assert_eq!(doc["ranges"][0]["range"], "2001:db8::/32");
+    let output = netrange(&[&args[..], &["--output-format", "csv"]].concat());
+    assert!(!output.status.success());
+
+    // The file was created long ago, but not before the Unix epoch
+    let args = &["cloud", "read", "aws", &file, "--max-source-age"];
+    assert!(!netrange(&[&args[..], &["3600"]].concat()).status.success());
+    stdout(&netrange(&[&args[..], &["100000000000"]].concat()));
+    let args = &[
+        "cloud",
+        "merge",
+        "aws",
+        &undated_file,
+        "--max-source-age",
+        "100000000000",
+    ];
+    let output = netrange(args);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't say when it was created"));
+}
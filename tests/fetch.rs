@@ -0,0 +1,524 @@
+//! Tests of downloading range files, run against a local HTTP server
+//! that stands in for the cloud services.
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tempfile::TempDir;
+
+const AWS_RANGES: &str = r#"{
+  "syncToken": "1614772800",
+  "createDate": "2021-03-03-12-00-00",
+  "prefixes": [
+    {"ip_prefix": "10.0.0.0/24", "region": "us-east-1", "service": "EC2", "network_border_group": "us-east-1"},
+    {"ip_prefix": "10.0.1.0/24", "region": "us-east-1", "service": "S3", "network_border_group": "us-east-1"}
+  ],
+  "ipv6_prefixes": []
+}"#;
+
+const GITHUB_META: &str = r#"{
+  "hooks": ["10.1.0.0/24"],
+  "web": ["10.1.1.0/24"],
+  "api": ["10.1.1.0/24"],
+  "git": ["10.1.1.0/24"],
+  "pages": [],
+  "importer": [],
+  "actions": [],
+  "dependabot": []
+}"#;
+
+const SERVICE_UNAVAILABLE: &str =
+    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+const RATE_LIMITED: &str = "HTTP/1.1 403 Forbidden\r\nX-RateLimit-Remaining: 0\r\n\
+    X-RateLimit-Reset: 1614772800\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+const ETAG: &str = "\"v1\"";
+
+/// A request received by the test server.
+#[derive(Clone, Debug)]
+struct Request {
+    path: String,
+    if_none_match: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// A minimal HTTP server that serves `body` with an ETag from every path
+/// and answers conditional requests for that ETag with "304 Not Modified".
+/// The first `failures` requests can be answered with an error response instead.
+struct Server {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    fn start(body: &'static str) -> Server {
+        Server::start_with_failures(body, 0, SERVICE_UNAVAILABLE)
+    }
+
+    fn start_with_failures(
+        body: &'static str,
+        failures: usize,
+        failure_response: &'static str,
+    ) -> Server {
+        let failures = AtomicUsize::new(failures);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or("")
+                    .to_string();
+                let mut if_none_match = None;
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let mut parts = line.splitn(2, ':');
+                    let name = parts.next().unwrap().trim().to_lowercase();
+                    let value = parts.next().unwrap_or("").trim().to_string();
+                    if name == "if-none-match" {
+                        if_none_match = Some(value.clone());
+                    }
+                    headers.push((name, value));
+                }
+
+                let response = if failures.load(Ordering::SeqCst) > 0 {
+                    failures.fetch_sub(1, Ordering::SeqCst);
+                    failure_response.to_string()
+                } else if if_none_match.as_deref() == Some(ETAG) {
+                    format!(
+                        "HTTP/1.1 304 Not Modified\r\nETag: {}\r\nConnection: close\r\n\r\n",
+                        ETAG
+                    )
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        ETAG,
+                        body.len(),
+                        body
+                    )
+                };
+                server_requests.lock().unwrap().push(Request {
+                    path,
+                    if_none_match,
+                    headers,
+                });
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        Server { addr, requests }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Run netrange with `args` in an environment that doesn't use a proxy
+/// or any of the user's netrange settings.
+fn netrange(args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_netrange"));
+    for (key, _) in env::vars() {
+        if key.starts_with("NETRANGE_")
+            || key == "GITHUB_TOKEN"
+            || key.to_lowercase().ends_with("_proxy")
+        {
+            command.env_remove(key);
+        }
+    }
+    command.args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "netrange failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_url_override() {
+    let server = Server::start(AWS_RANGES);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+
+    let output = netrange(&["cloud", "get", "aws", "--no-cache", "--url", &url]);
+    assert_eq!(stdout(&output), AWS_RANGES);
+
+    let output = netrange(&["cloud", "get-merge", "aws", "--no-cache", "--url", &url]);
+    assert_eq!(stdout(&output), "10.0.0.0/23\n");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|r| r.path == "/ip-ranges.json"));
+}
+
+#[test]
+fn test_conditional_request() {
+    let server = Server::start(AWS_RANGES);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+    let cache_dir = TempDir::new().unwrap();
+    let cache_dir = cache_dir.path().to_str().unwrap();
+
+    let args = &[
+        "cloud",
+        "get",
+        "aws",
+        "--cache-dir",
+        cache_dir,
+        "--url",
+        &url,
+    ];
+    assert_eq!(stdout(&netrange(args)), AWS_RANGES);
+    assert_eq!(stdout(&netrange(args)), AWS_RANGES);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].if_none_match, None);
+    assert_eq!(requests[1].if_none_match.as_deref(), Some(ETAG));
+
+    // A fresh enough cached file is used without a request
+    let args = &[
+        "cloud",
+        "get",
+        "aws",
+        "--cache-dir",
+        cache_dir,
+        "--url",
+        &url,
+        "--max-age",
+        "3600",
+    ];
+    assert_eq!(stdout(&netrange(args)), AWS_RANGES);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn test_mirror() {
+    let server = Server::start(AWS_RANGES);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+    let mirror_dir = TempDir::new().unwrap();
+    let mirror_dir_str = mirror_dir.path().to_str().unwrap();
+
+    let output = netrange(&[
+        "cloud",
+        "mirror-populate",
+        "aws",
+        "--no-cache",
+        "--mirror-dir",
+        mirror_dir_str,
+        "--url",
+        &url,
+    ]);
+    assert_eq!(
+        stdout(&output).trim(),
+        mirror_dir.path().join("aws.json").to_str().unwrap()
+    );
+    assert_eq!(
+        fs::read_to_string(mirror_dir.path().join("aws.json")).unwrap(),
+        AWS_RANGES
+    );
+    assert_eq!(server.requests().len(), 1);
+
+    // The mirror is used instead of the network, so the
+    // unreachable URL is never requested
+    let output = netrange(&[
+        "cloud",
+        "get-merge",
+        "aws",
+        "--mirror-dir",
+        mirror_dir_str,
+        "--url",
+        "aws=http://127.0.0.1:1/ip-ranges.json",
+    ]);
+    assert_eq!(stdout(&output), "10.0.0.0/23\n");
+
+    let output = netrange(&["cloud", "get", "gcp", "--mirror-dir", mirror_dir_str]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_mirror_populate_invalid_file() {
+    let server = Server::start("not a range file");
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+    let mirror_dir = TempDir::new().unwrap();
+
+    let output = netrange(&[
+        "cloud",
+        "mirror-populate",
+        "aws",
+        "--no-cache",
+        "--mirror-dir",
+        mirror_dir.path().to_str().unwrap(),
+        "--url",
+        &url,
+    ]);
+    assert!(!output.status.success());
+    assert!(!mirror_dir.path().join("aws.json").exists());
+}
+
+#[test]
+fn test_retries() {
+    let server = Server::start_with_failures(AWS_RANGES, 2, SERVICE_UNAVAILABLE);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+
+    let output = netrange(&[
+        "cloud",
+        "get-merge",
+        "aws",
+        "--no-cache",
+        "--url",
+        &url,
+        "--retries",
+        "2",
+        "--retry-delay",
+        "10",
+    ]);
+    assert_eq!(stdout(&output), "10.0.0.0/23\n");
+    assert_eq!(server.requests().len(), 3);
+
+    let server = Server::start_with_failures(AWS_RANGES, 1, SERVICE_UNAVAILABLE);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+    let output = netrange(&[
+        "cloud",
+        "get",
+        "aws",
+        "--no-cache",
+        "--url",
+        &url,
+        "--retries",
+        "0",
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn test_headers() {
+    let server = Server::start(AWS_RANGES);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+
+    let output = netrange(&[
+        "cloud",
+        "get",
+        "aws",
+        "--no-cache",
+        "--url",
+        &url,
+        "--header",
+        "X-Test: 1",
+        "--header",
+        "User-Agent: custom",
+    ]);
+    assert_eq!(stdout(&output), AWS_RANGES);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("x-test"), Some("1"));
+    assert_eq!(requests[0].header("user-agent"), Some("custom"));
+}
+
+#[test]
+fn test_github_enterprise() {
+    let server = Server::start(GITHUB_META);
+
+    let output = netrange(&[
+        "cloud",
+        "get-merge",
+        "github",
+        "--no-cache",
+        "--github-url",
+        &server.url(""),
+        "--github-token",
+        "secret",
+    ]);
+    assert_eq!(stdout(&output), "10.1.0.0/23\n");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/api/v3/meta");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+}
+
+#[test]
+fn test_github_url_override() {
+    let server = Server::start(GITHUB_META);
+    let url = format!("github={}", server.url("/mirror/meta"));
+
+    let output = netrange(&[
+        "cloud",
+        "get-merge",
+        "github",
+        "--no-cache",
+        "--url",
+        &url,
+        "--github-token",
+        "secret",
+    ]);
+    assert_eq!(stdout(&output), "10.1.0.0/23\n");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/mirror/meta");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+}
+
+#[test]
+fn test_url_override_cache() {
+    let server = Server::start(AWS_RANGES);
+    let url = format!("aws={}", server.url("/ip-ranges.json"));
+    let other_url = format!("aws={}", server.url("/other.json"));
+    let cache_dir = TempDir::new().unwrap();
+    let cache_dir = cache_dir.path().to_str().unwrap();
+
+    let args = &["cloud", "get", "aws", "--cache-dir", cache_dir];
+    assert_eq!(
+        stdout(&netrange(&[&args[..], &["--url", &url]].concat())),
+        AWS_RANGES
+    );
+
+    // The file from the overridden URL is only used for that URL
+    let offline = &[&args[..], &["--offline"]].concat();
+    assert_eq!(
+        stdout(&netrange(&[&offline[..], &["--url", &url]].concat())),
+        AWS_RANGES
+    );
+    assert!(!netrange(offline).status.success());
+    assert!(!netrange(&[&offline[..], &["--url", &other_url]].concat())
+        .status
+        .success());
+
+    let fresh = &[&args[..], &["--max-age", "3600", "--url", &other_url]].concat();
+    assert_eq!(stdout(&netrange(fresh)), AWS_RANGES);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].path, "/other.json");
+    assert_eq!(requests[1].if_none_match, None);
+}
+
+#[test]
+fn test_github_enterprise_cache() {
+    let server = Server::start(GITHUB_META);
+    let cache_dir = TempDir::new().unwrap();
+    let cache_dir = cache_dir.path().to_str().unwrap();
+    let archive_dir = TempDir::new().unwrap();
+    let archive_dir = archive_dir.path().to_str().unwrap();
+
+    let args = &["cloud", "get", "github", "--cache-dir", cache_dir];
+    let enterprise = &["--github-url", &server.url("")];
+    let output = netrange(&[&args[..], enterprise, &["--archive-dir", archive_dir]].concat());
+    assert_eq!(stdout(&output), GITHUB_META);
+
+    // github.com's ranges are never served from the enterprise server's file
+    assert!(!netrange(&[&args[..], &["--offline"]].concat())
+        .status
+        .success());
+    let output = netrange(&[&args[..], enterprise, &["--offline"]].concat());
+    assert_eq!(stdout(&output), GITHUB_META);
+
+    let as_of = &["--archive-dir", archive_dir, "--as-of", "4000000000"];
+    assert!(!netrange(&[&args[..], as_of].concat()).status.success());
+    let output = netrange(&[&args[..], enterprise, as_of].concat());
+    assert_eq!(stdout(&output), GITHUB_META);
+}
+
+#[test]
+fn test_github_rate_limit() {
+    let server = Server::start_with_failures(GITHUB_META, 1, RATE_LIMITED);
+
+    let output = netrange(&[
+        "cloud",
+        "get",
+        "github",
+        "--no-cache",
+        "--github-url",
+        &server.url(""),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("--github-token"), "{}", stderr);
+    assert!(
+        stderr.contains("resets at 2021-03-03T12:00:00Z"),
+        "{}",
+        stderr
+    );
+    assert_eq!(server.requests()[0].header("authorization"), None);
+}
+
+#[test]
+fn test_multiple_services() {
+    let aws = Server::start(AWS_RANGES);
+    let github = Server::start(GITHUB_META);
+    let aws_url = format!("aws={}", aws.url("/ip-ranges.json"));
+    let github_url = format!("github={}", github.url("/meta"));
+
+    let output = netrange(&[
+        "cloud",
+        "get-merge",
+        "aws,github",
+        "--no-cache",
+        "--url",
+        &aws_url,
+        "--url",
+        &github_url,
+    ]);
+    assert_eq!(stdout(&output), "10.0.0.0/23\n10.1.0.0/23\n");
+
+    let output = netrange(&[
+        "cloud",
+        "get-merge",
+        "aws,github",
+        "--no-cache",
+        "--url",
+        &aws_url,
+        "--url",
+        &github_url,
+        "--filter",
+        "return provider == 'github'",
+    ]);
+    assert_eq!(stdout(&output), "10.1.0.0/23\n");
+
+    let output = netrange(&[
+        "cloud",
+        "get-read",
+        "aws",
+        "github",
+        "--no-cache",
+        "--url",
+        &aws_url,
+        "--url",
+        &github_url,
+        "--filter",
+        "return provider == 'aws' and service == 'S3'",
+    ]);
+    assert_eq!(stdout(&output), "10.0.1.0/24\n");
+}